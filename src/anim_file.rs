@@ -0,0 +1,889 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use bevy::math::Vec2;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The schema version written by this build. Files without a `version` field are version 0.
+pub const CURRENT_VERSION: u32 = 3;
+
+/// Upgrades a file from version `i` to `i + 1`, indexed by `i`.
+const MIGRATIONS: [fn(&mut Value); CURRENT_VERSION as usize] = [v0_to_v1, v1_to_v2, v2_to_v3];
+
+/// Version 1 renamed `Hitbox::desc` to `Hitbox::tag`.
+fn v0_to_v1(file: &mut Value) {
+    let Some(hitboxes) = file
+        .pointer_mut("/info/hitboxes")
+        .and_then(Value::as_object_mut)
+    else {
+        return;
+    };
+    for hitbox in hitboxes.values_mut().filter_map(Value::as_object_mut) {
+        if let Some(desc) = hitbox.remove("desc") {
+            hitbox.insert("tag".into(), desc);
+        }
+    }
+}
+
+/// Version 2 allows `spritesheet` to reference an image file instead of embedding it, older
+/// files are valid as they are.
+fn v1_to_v2(_: &mut Value) {}
+
+/// Version 3 replaced `Hitbox::is_hurtbox` with `Hitbox::kind`.
+fn v2_to_v3(file: &mut Value) {
+    let Some(hitboxes) = file
+        .pointer_mut("/info/hitboxes")
+        .and_then(Value::as_object_mut)
+    else {
+        return;
+    };
+    for hitbox in hitboxes.values_mut().filter_map(Value::as_object_mut) {
+        if let Some(is_hurtbox) = hitbox.remove("is_hurtbox") {
+            let kind = if is_hurtbox.as_bool() == Some(true) {
+                "hurtbox"
+            } else {
+                "hitbox"
+            };
+            hitbox.insert("kind".into(), kind.into());
+        }
+    }
+}
+
+/// How an animation file is stored on disk, picked from its extension.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    /// `.anim`, JSON with the spritesheet in base64.
+    Json,
+    /// `.animb`, MessagePack with the spritesheet as raw bytes.
+    Binary,
+}
+
+impl Encoding {
+    pub const EXTENSIONS: [&'static str; 2] = ["anim", "animb"];
+
+    pub fn from_path(path: &Path) -> Option<Encoding> {
+        match path.extension()?.to_str()? {
+            "anim" => Some(Encoding::Json),
+            "animb" => Some(Encoding::Binary),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AnimationFileData {
+    pub version: u32,
+    pub spritesheet: SpritesheetSource,
+    pub info: Info,
+}
+
+/// Where the spritesheet of an animation comes from. In files this is either the image data
+/// itself or `{ "path": "..." }`, with the path relative to the animation file.
+pub enum SpritesheetSource {
+    Embedded(seethe::EncodedBytes),
+    External { path: String },
+}
+
+impl Serialize for SpritesheetSource {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        match self {
+            SpritesheetSource::Embedded(bytes) => bytes.serialize(s),
+            SpritesheetSource::External { path } => {
+                let mut map = s.serialize_map(Some(1))?;
+                map.serialize_entry("path", path)?;
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'d> Deserialize<'d> for SpritesheetSource {
+    fn deserialize<D: serde::Deserializer<'d>>(d: D) -> Result<Self, D::Error> {
+        use seethe::EncodedBytes;
+        use serde::de::{Error, MapAccess, Visitor};
+
+        struct V;
+
+        impl<'d> Visitor<'d> for V {
+            type Value = SpritesheetSource;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("image data or a map with a `path`")
+            }
+
+            fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(SpritesheetSource::Embedded(EncodedBytes::Raw(v)))
+            }
+
+            fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(SpritesheetSource::Embedded(EncodedBytes::Raw(v.into())))
+            }
+
+            fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(SpritesheetSource::Embedded(EncodedBytes::Base64(v.into())))
+            }
+
+            fn visit_map<A: MapAccess<'d>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut path = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "path" => path = Some(map.next_value()?),
+                        other => return Err(A::Error::unknown_field(other, &["path"])),
+                    }
+                }
+                let path = path.ok_or_else(|| A::Error::missing_field("path"))?;
+                Ok(SpritesheetSource::External { path })
+            }
+        }
+
+        d.deserialize_any(V)
+    }
+}
+
+pub mod seethe {
+    use std::borrow::Cow;
+
+    use base64::Engine;
+    use serde::{de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Binary data as it appears in the file. Text formats store base64, which is only decoded
+    /// once the file has parsed so that bad encodings can be reported with the field they are
+    /// in.
+    pub enum EncodedBytes {
+        Base64(String),
+        Raw(Vec<u8>),
+    }
+
+    impl EncodedBytes {
+        pub fn decode(&self) -> Result<Cow<'_, [u8]>, base64::DecodeError> {
+            match self {
+                EncodedBytes::Base64(s) => base64::engine::general_purpose::STANDARD_NO_PAD
+                    .decode(s)
+                    .map(Cow::Owned),
+                EncodedBytes::Raw(bytes) => Ok(Cow::Borrowed(bytes)),
+            }
+        }
+    }
+
+    impl Serialize for EncodedBytes {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            match self {
+                EncodedBytes::Base64(text) if s.is_human_readable() => s.serialize_str(text),
+                EncodedBytes::Raw(bytes) if s.is_human_readable() => {
+                    s.serialize_str(&base64::engine::general_purpose::STANDARD_NO_PAD.encode(bytes))
+                }
+                _ => s.serialize_bytes(&self.decode().map_err(serde::ser::Error::custom)?),
+            }
+        }
+    }
+
+    impl<'d> Deserialize<'d> for EncodedBytes {
+        fn deserialize<D: Deserializer<'d>>(d: D) -> Result<Self, D::Error> {
+            struct V;
+
+            impl Visitor<'_> for V {
+                type Value = EncodedBytes;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    formatter.write_str("data")
+                }
+
+                fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+                where
+                    E: serde::de::Error,
+                {
+                    Ok(EncodedBytes::Raw(v))
+                }
+
+                fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+                where
+                    E: serde::de::Error,
+                {
+                    Ok(EncodedBytes::Raw(v.into()))
+                }
+
+                fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+                where
+                    E: serde::de::Error,
+                {
+                    Ok(EncodedBytes::Base64(v.into()))
+                }
+            }
+
+            if d.is_human_readable() {
+                d.deserialize_str(V)
+            } else {
+                d.deserialize_byte_buf(V)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Info {
+    pub cell_width: usize,
+    pub cell_height: usize,
+    pub columns: usize,
+    pub frame_count: usize,
+    pub frame_data: Vec<FrameData>,
+    pub hitboxes: HashMap<usize, Hitbox>,
+    /// Whether playing the animation moves the character by `FrameData::root_motion`.
+    #[serde(default)]
+    pub use_root_motion: bool,
+    #[serde(default)]
+    pub playback: PlaybackMode,
+    /// Multiplier on how fast frames advance, 2.0 plays every delay in half the time.
+    #[serde(default = "one")]
+    pub speed: f32,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+/// What an animation does once its last frame is over.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybackMode {
+    #[default]
+    Loop,
+    /// Stays on the last frame.
+    OnceThenHold,
+    /// Ends the current move and returns the character to idle.
+    OnceThenReturn,
+    /// Plays backwards to the first frame, then forwards again.
+    PingPong,
+    /// Jumps back to the given frame instead of the first one.
+    LoopFrom(usize),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FrameData {
+    pub delay: usize,
+    pub origin: Vec2,
+    /// How far the character is from where it was on the first frame, when facing right. Only
+    /// applied with `Info::use_root_motion`.
+    pub root_motion: Vec2,
+    pub hitboxes: HashMap<usize, HitboxPos>,
+    #[serde(default)]
+    pub events: Vec<FrameEvent>,
+    #[serde(default)]
+    pub flags: FrameFlags,
+}
+
+/// Properties of the character for as long as a frame is shown.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct FrameFlags {
+    pub strike_invulnerable: bool,
+    pub throw_invulnerable: bool,
+    pub projectile_invulnerable: bool,
+    /// Counts the character as airborne even while their feet are on the ground, so they
+    /// can't be thrown and air-only moves can be used.
+    pub airborne: bool,
+}
+
+/// Something that happens when a frame is entered. Directions are relative to the facing of
+/// the character, with positive x pointing forward.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameEvent {
+    SpawnProjectile(ProjectileSpawn),
+    PlaySound(String),
+    SetVelocity(Vec2),
+    AddVelocity(Vec2),
+    /// Makes the character ignore hits for this many frames.
+    Invulnerable(usize),
+    Tag(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProjectileSpawn {
+    /// Name of an entry in the character's animation table.
+    pub animation: String,
+    pub offset: Vec2,
+    pub velocity: Vec2,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Hitbox {
+    pub id: usize,
+    pub tag: String,
+    pub kind: HitboxKind,
+    /// Entry of the move's `attacks` table applied when this box hits. Boxes without one apply
+    /// the move's own effects.
+    #[serde(default)]
+    pub attack: Option<String>,
+    /// When several boxes of a move hit the same target on one frame, only the one with the
+    /// highest priority applies, the lowest id winning ties.
+    #[serde(default)]
+    pub priority: i32,
+}
+
+/// What a box is for. Which kinds touch each other is decided by the game's collision matrix.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum HitboxKind {
+    /// Where the character can be struck.
+    Hurtbox,
+    /// Strikes hurtboxes.
+    Hitbox,
+    /// Grabs throwable boxes.
+    Throwbox,
+    /// Where the character can be grabbed.
+    Throwable,
+    /// The character's body, which other bodies can't overlap and push out of the way.
+    Pushbox,
+    /// Where an opponent holding back guards instead of walking away, usually reaching a bit
+    /// past the hitboxes of the attack.
+    Proximity,
+    /// The part of the character kept inside the stage walls.
+    Collision,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HitboxPos {
+    pub id: usize,
+    pub pos: Vec2,
+    pub size: Vec2,
+    pub enabled: bool,
+}
+
+/// A `.anim` file that parsed and passed validation, with an embedded spritesheet decoded.
+pub struct ParsedAnimation {
+    pub info: Info,
+    pub spritesheet: ParsedSpritesheet,
+}
+
+pub enum ParsedSpritesheet {
+    Embedded(DynamicImage),
+    /// Path of the image, resolved against the directory of the animation file.
+    External(PathBuf),
+}
+
+#[derive(Debug)]
+pub struct AnimationError {
+    pub path: PathBuf,
+    pub kind: AnimationErrorKind,
+}
+
+#[derive(Debug)]
+pub enum AnimationErrorKind {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    Decode(rmp_serde::decode::Error),
+    Encode(rmp_serde::encode::Error),
+    UnsupportedVersion(u32),
+    MissingSpritesheet {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Base64 {
+        field: &'static str,
+        error: base64::DecodeError,
+    },
+    Image {
+        field: &'static str,
+        error: image::ImageError,
+    },
+    FrameCountMismatch {
+        frame_count: usize,
+        frame_data: usize,
+    },
+    MissingHitbox {
+        frame: usize,
+        id: usize,
+    },
+    ZeroDelay {
+        frame: usize,
+    },
+    ZeroColumns,
+    LoopFromOutOfRange {
+        frame: usize,
+    },
+    InvalidSpeed(f32),
+}
+
+impl Display for AnimationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.path.display())?;
+        match &self.kind {
+            AnimationErrorKind::Io(error) => write!(f, "could not read file: {error}"),
+            AnimationErrorKind::Parse(error) => write!(f, "invalid animation file: {error}"),
+            AnimationErrorKind::Decode(error) => write!(f, "invalid binary animation file: {error}"),
+            AnimationErrorKind::Encode(error) => write!(f, "could not encode animation: {error}"),
+            AnimationErrorKind::UnsupportedVersion(version) => write!(
+                f,
+                "field `version` is {version}, but the newest supported version is {CURRENT_VERSION}"
+            ),
+            AnimationErrorKind::MissingSpritesheet { path, error } => write!(
+                f,
+                "could not read spritesheet `{}`: {error}",
+                path.display()
+            ),
+            AnimationErrorKind::Base64 { field, error } => {
+                write!(f, "field `{field}` is not valid base64: {error}")
+            }
+            AnimationErrorKind::Image { field, error } => {
+                write!(f, "field `{field}` is not a readable image: {error}")
+            }
+            AnimationErrorKind::FrameCountMismatch {
+                frame_count,
+                frame_data,
+            } => write!(
+                f,
+                "field `info.frame_count` is {frame_count} but `info.frame_data` has {frame_data} frames"
+            ),
+            AnimationErrorKind::MissingHitbox { frame, id } => write!(
+                f,
+                "frame {frame} uses hitbox {id}, which is missing from `info.hitboxes`"
+            ),
+            AnimationErrorKind::ZeroDelay { frame } => {
+                write!(f, "frame {frame} has a `delay` of 0")
+            }
+            AnimationErrorKind::ZeroColumns => {
+                write!(f, "field `info.columns` is 0, but the spritesheet needs at least one")
+            }
+            AnimationErrorKind::LoopFromOutOfRange { frame } => write!(
+                f,
+                "field `info.playback` loops from frame {frame}, which does not exist"
+            ),
+            AnimationErrorKind::InvalidSpeed(speed) => {
+                write!(f, "field `info.speed` is {speed}, but has to be above 0")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AnimationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            AnimationErrorKind::Io(error) => Some(error),
+            AnimationErrorKind::Parse(error) => Some(error),
+            AnimationErrorKind::Decode(error) => Some(error),
+            AnimationErrorKind::Encode(error) => Some(error),
+            AnimationErrorKind::MissingSpritesheet { error, .. } => Some(error),
+            AnimationErrorKind::Base64 { error, .. } => Some(error),
+            AnimationErrorKind::Image { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl AnimationFileData {
+    pub fn read(encoding: Encoding, bytes: &[u8]) -> Result<Self, AnimationErrorKind> {
+        match encoding {
+            Encoding::Json => Self::from_json(bytes),
+            Encoding::Binary => Self::from_binary(bytes),
+        }
+    }
+
+    pub fn write(&self, encoding: Encoding) -> Result<Vec<u8>, AnimationErrorKind> {
+        match encoding {
+            Encoding::Json => serde_json::to_vec_pretty(self).map_err(AnimationErrorKind::Parse),
+            Encoding::Binary => rmp_serde::to_vec_named(self).map_err(AnimationErrorKind::Encode),
+        }
+    }
+
+    /// Reads a file of any supported version, migrating it to the current schema.
+    fn from_json(bytes: &[u8]) -> Result<Self, AnimationErrorKind> {
+        let mut file: Value = serde_json::from_slice(bytes).map_err(AnimationErrorKind::Parse)?;
+        let version = match file.get("version") {
+            None => 0,
+            Some(version) => {
+                serde_json::from_value(version.clone()).map_err(AnimationErrorKind::Parse)?
+            }
+        };
+        if version > CURRENT_VERSION {
+            return Err(AnimationErrorKind::UnsupportedVersion(version));
+        }
+
+        for migration in &MIGRATIONS[version as usize..] {
+            migration(&mut file);
+        }
+        if let Some(file) = file.as_object_mut() {
+            file.insert("version".into(), CURRENT_VERSION.into());
+        }
+
+        serde_json::from_value(file).map_err(AnimationErrorKind::Parse)
+    }
+
+    /// Binary files are packed from JSON sources, so they are only read at the current
+    /// version. Older ones have to be packed again from their upgraded source.
+    fn from_binary(bytes: &[u8]) -> Result<Self, AnimationErrorKind> {
+        #[derive(Deserialize)]
+        struct Header {
+            #[serde(default)]
+            version: u32,
+        }
+
+        let header: Header = rmp_serde::from_slice(bytes).map_err(AnimationErrorKind::Decode)?;
+        if header.version != CURRENT_VERSION {
+            return Err(AnimationErrorKind::UnsupportedVersion(header.version));
+        }
+        rmp_serde::from_slice(bytes).map_err(AnimationErrorKind::Decode)
+    }
+}
+
+impl Info {
+    fn validate(&self) -> Result<(), AnimationErrorKind> {
+        if self.frame_count != self.frame_data.len() {
+            return Err(AnimationErrorKind::FrameCountMismatch {
+                frame_count: self.frame_count,
+                frame_data: self.frame_data.len(),
+            });
+        }
+        if self.columns == 0 {
+            return Err(AnimationErrorKind::ZeroColumns);
+        }
+
+        match self.playback {
+            PlaybackMode::LoopFrom(frame) if frame >= self.frame_count => {
+                return Err(AnimationErrorKind::LoopFromOutOfRange { frame });
+            }
+            _ => {}
+        }
+        if self.speed.is_nan() || self.speed <= 0.0 {
+            return Err(AnimationErrorKind::InvalidSpeed(self.speed));
+        }
+
+        for (frame, data) in self.frame_data.iter().enumerate() {
+            if data.delay == 0 {
+                return Err(AnimationErrorKind::ZeroDelay { frame });
+            }
+
+            let mut ids: Vec<_> = data.hitboxes.values().map(|hp| hp.id).collect();
+            ids.sort();
+            if let Some(&id) = ids.iter().find(|id| !self.hitboxes.contains_key(id)) {
+                return Err(AnimationErrorKind::MissingHitbox { frame, id });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses and validates an animation file, with the encoding picked from the extension of
+/// `path`.
+pub fn parse(path: &Path, bytes: &[u8]) -> Result<ParsedAnimation, AnimationError> {
+    let error = |kind| AnimationError {
+        path: path.to_path_buf(),
+        kind,
+    };
+
+    let encoding = Encoding::from_path(path).unwrap_or(Encoding::Json);
+    let file = AnimationFileData::read(encoding, bytes).map_err(error)?;
+    file.info.validate().map_err(error)?;
+
+    let spritesheet = match &file.spritesheet {
+        SpritesheetSource::Embedded(bytes) => {
+            let bytes = bytes.decode().map_err(|e| {
+                error(AnimationErrorKind::Base64 {
+                    field: "spritesheet",
+                    error: e,
+                })
+            })?;
+            let image = image::load_from_memory(&bytes).map_err(|e| {
+                error(AnimationErrorKind::Image {
+                    field: "spritesheet",
+                    error: e,
+                })
+            })?;
+            ParsedSpritesheet::Embedded(image)
+        }
+        SpritesheetSource::External { path: image } => {
+            ParsedSpritesheet::External(path.parent().unwrap_or(Path::new("")).join(image))
+        }
+    };
+
+    Ok(ParsedAnimation {
+        info: file.info,
+        spritesheet,
+    })
+}
+
+/// Parses every animation file below `dir`, returning the errors of all that fail.
+pub fn validate_assets(dir: &Path) -> Vec<AnimationError> {
+    let mut errors = vec![];
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            errors.push(AnimationError {
+                path: dir.to_path_buf(),
+                kind: AnimationErrorKind::Io(e),
+            });
+            return errors;
+        }
+    };
+
+    let mut paths: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            errors.extend(validate_assets(&path));
+        } else if Encoding::from_path(&path).is_some() {
+            let result = std::fs::read(&path)
+                .map_err(|e| AnimationError {
+                    path: path.clone(),
+                    kind: AnimationErrorKind::Io(e),
+                })
+                .and_then(|bytes| parse(&path, &bytes))
+                .and_then(|parsed| check_spritesheet(&path, parsed.spritesheet));
+            if let Err(e) = result {
+                errors.push(e);
+            }
+        }
+    }
+    errors
+}
+
+/// Makes sure an external spritesheet exists and decodes, which loading leaves to the asset
+/// server.
+fn check_spritesheet(path: &Path, spritesheet: ParsedSpritesheet) -> Result<(), AnimationError> {
+    let ParsedSpritesheet::External(image) = spritesheet else {
+        return Ok(());
+    };
+    let error = |kind| AnimationError {
+        path: path.to_path_buf(),
+        kind,
+    };
+
+    let bytes = std::fs::read(&image).map_err(|e| {
+        error(AnimationErrorKind::MissingSpritesheet {
+            path: image.clone(),
+            error: e,
+        })
+    })?;
+    image::load_from_memory(&bytes).map_err(|e| {
+        error(AnimationErrorKind::Image {
+            field: "spritesheet.path",
+            error: e,
+        })
+    })?;
+    Ok(())
+}
+
+/// Rewrites the JSON `.anim` file at `path` in the current schema. Returns whether the file was
+/// changed, files that are already current are left untouched.
+pub fn upgrade_file(path: &Path) -> Result<bool, AnimationError> {
+    let error = |kind| AnimationError {
+        path: path.to_path_buf(),
+        kind,
+    };
+
+    let bytes = std::fs::read(path).map_err(|e| error(AnimationErrorKind::Io(e)))?;
+    let old: Value =
+        serde_json::from_slice(&bytes).map_err(|e| error(AnimationErrorKind::Parse(e)))?;
+    if old.get("version") == Some(&Value::from(CURRENT_VERSION)) {
+        return Ok(false);
+    }
+
+    let file = AnimationFileData::from_json(&bytes).map_err(error)?;
+    file.info.validate().map_err(error)?;
+    let bytes = file.write(Encoding::Json).map_err(error)?;
+    std::fs::write(path, bytes).map_err(|e| error(AnimationErrorKind::Io(e)))?;
+    Ok(true)
+}
+
+/// Converts between the JSON and binary forms, picking both encodings from the extensions.
+/// The contents of `from` are validated before anything is written to `to`.
+pub fn convert(from: &Path, to: &Path) -> Result<(), AnimationError> {
+    let read_error = |kind| AnimationError {
+        path: from.to_path_buf(),
+        kind,
+    };
+    let write_error = |kind| AnimationError {
+        path: to.to_path_buf(),
+        kind,
+    };
+
+    let bytes = std::fs::read(from).map_err(|e| read_error(AnimationErrorKind::Io(e)))?;
+    let file = AnimationFileData::read(Encoding::from_path(from).unwrap_or(Encoding::Json), &bytes)
+        .map_err(read_error)?;
+    file.info.validate().map_err(read_error)?;
+
+    let bytes = file
+        .write(Encoding::from_path(to).unwrap_or(Encoding::Json))
+        .map_err(write_error)?;
+    std::fs::write(to, bytes).map_err(|e| write_error(AnimationErrorKind::Io(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assets() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("assets")
+    }
+
+    /// Writes `file` into an empty directory of its own, so it can be validated alone.
+    fn write_malformed(name: &str, file: &Value) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fg-anim-file-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{name}.anim"));
+        std::fs::write(&path, serde_json::to_vec(file).unwrap()).unwrap();
+        path
+    }
+
+    fn punch() -> Value {
+        serde_json::from_slice(&std::fs::read(assets().join("c1_punch.anim")).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn assets_are_valid() {
+        let errors = validate_assets(&assets());
+        assert!(errors.is_empty(), "{errors:?}");
+    }
+
+    #[test]
+    fn zero_delay_names_file_and_frame() {
+        let mut file = punch();
+        file["info"]["frame_data"][3]["delay"] = 0.into();
+        let path = write_malformed("zero_delay", &file);
+
+        let errors = validate_assets(path.parent().unwrap());
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(errors[0].path, path);
+        assert!(matches!(
+            errors[0].kind,
+            AnimationErrorKind::ZeroDelay { frame: 3 }
+        ));
+    }
+
+    #[test]
+    fn bad_base64_names_file_and_field() {
+        let mut file = punch();
+        file["spritesheet"] = "not base64!".into();
+        let path = write_malformed("bad_base64", &file);
+
+        let errors = validate_assets(path.parent().unwrap());
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(errors[0].path, path);
+        assert!(matches!(
+            errors[0].kind,
+            AnimationErrorKind::Base64 {
+                field: "spritesheet",
+                ..
+            }
+        ));
+    }
+
+    /// Checks that `file` is reported with exactly one error, of the kind `check` accepts.
+    fn assert_single_error(name: &str, file: &Value, check: impl Fn(&AnimationErrorKind) -> bool) {
+        let path = write_malformed(name, file);
+        let errors = validate_assets(path.parent().unwrap());
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(errors[0].path, path);
+        assert!(check(&errors[0].kind), "{errors:?}");
+    }
+
+    #[test]
+    fn undecodable_png_names_the_field() {
+        let mut file = punch();
+        // Valid base64 of bytes that aren't an image.
+        file["spritesheet"] = "bm90IGFuIGltYWdl".into();
+        assert_single_error("bad_png", &file, |kind| {
+            matches!(
+                kind,
+                AnimationErrorKind::Image {
+                    field: "spritesheet",
+                    ..
+                }
+            )
+        });
+    }
+
+    #[test]
+    fn frame_count_must_match_the_frames() {
+        let mut file = punch();
+        file["info"]["frame_count"] = 3.into();
+        assert_single_error("frame_count", &file, |kind| {
+            matches!(
+                kind,
+                AnimationErrorKind::FrameCountMismatch {
+                    frame_count: 3,
+                    frame_data: 9,
+                }
+            )
+        });
+    }
+
+    #[test]
+    fn frame_using_a_missing_hitbox_names_frame_and_id() {
+        let mut file = punch();
+        file["info"]["frame_data"][2]["hitboxes"]["99"] = serde_json::json!({
+            "id": 99,
+            "pos": [0.0, 0.0],
+            "size": [1.0, 1.0],
+            "enabled": true,
+        });
+        assert_single_error("missing_hitbox", &file, |kind| {
+            matches!(kind, AnimationErrorKind::MissingHitbox { frame: 2, id: 99 })
+        });
+    }
+
+    #[test]
+    fn zero_columns_is_an_error() {
+        let mut file = punch();
+        file["info"]["columns"] = 0.into();
+        assert_single_error("zero_columns", &file, |kind| {
+            matches!(kind, AnimationErrorKind::ZeroColumns)
+        });
+    }
+
+    #[test]
+    fn binary_round_trip_is_lossless() {
+        let dir = std::env::temp_dir().join("fg-anim-file-round-trip");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let read = |path: &Path| {
+            let encoding = Encoding::from_path(path).unwrap();
+            let file = AnimationFileData::read(encoding, &std::fs::read(path).unwrap()).unwrap();
+            serde_json::to_value(file).unwrap()
+        };
+
+        for name in ["c1_idle", "c1_punch", "c1_walking", "c1_walking_v2"] {
+            let original = assets().join(format!("{name}.anim"));
+            let binary = dir.join(format!("{name}.animb"));
+            let back = dir.join(format!("{name}.anim"));
+            convert(&original, &binary).unwrap();
+            convert(&binary, &back).unwrap();
+
+            assert_eq!(read(&binary), read(&original), "{name}");
+            assert_eq!(read(&back), read(&original), "{name}");
+        }
+    }
+
+    #[test]
+    fn oldest_format_is_upgraded_on_parse() {
+        // The punch as it was saved before files had a version, with `desc` and `is_hurtbox`.
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/c1_punch_v0.anim");
+        let parsed = parse(&path, &std::fs::read(&path).unwrap()).unwrap();
+
+        let mut hitboxes: Vec<_> = parsed
+            .info
+            .hitboxes
+            .values()
+            .map(|hb| (hb.id, hb.tag.as_str(), hb.kind))
+            .collect();
+        hitboxes.sort_by_key(|&(id, ..)| id);
+        assert_eq!(
+            hitboxes,
+            [
+                (0, "Hitbox 0", HitboxKind::Hurtbox),
+                (1, "Hitbox 1", HitboxKind::Hurtbox),
+                (2, "Jab", HitboxKind::Hitbox),
+            ]
+        );
+        assert_eq!(parsed.info.frame_data.len(), 9);
+        assert!(matches!(parsed.spritesheet, ParsedSpritesheet::Embedded(_)));
+    }
+}
@@ -1,24 +1,34 @@
 use std::collections::HashMap;
 
 use bevy::{
-    asset::{Asset, AssetLoader, LoadedAsset},
+    asset::{Asset, AssetLoader, AssetPath, LoadedAsset},
     prelude::*,
     reflect::TypeUuid,
     sprite::Anchor,
     utils::BoxedFuture,
 };
-use image::DynamicImage;
-use serde::{Deserialize, Serialize};
 
 use crate::{
+    anim_file::{
+        self, FrameEvent, FrameFlags, Hitbox, HitboxKind, HitboxPos, ParsedAnimation,
+        ParsedSpritesheet, PlaybackMode,
+    },
     character::{Character, CharacterState, FacingDirection},
+    events::WhiffEvent,
     fixedpoint::FixedPoint,
+    physics::PushObstacles,
+    rules::Rules,
+    stage::Stage,
+    tag::Tag,
     Frameticker, Position,
 };
 
 pub fn init(app: &mut App) {
     app.add_asset::<Animation>();
     app.add_asset_loader(AnimationLoader);
+    app.add_event::<AnimationEvent>();
+    app.add_system(reload_animations);
+    app.add_system(reload_spritesheets);
 }
 
 struct AnimationLoader;
@@ -30,19 +40,33 @@ impl AssetLoader for AnimationLoader {
         load_context: &'a mut bevy::asset::LoadContext,
     ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
         Box::pin(async {
-            let anim: AnimationFileData = serde_json::from_slice(bytes)?;
-
-            let sprite_sheet = image::load_from_memory(&anim.spritesheet).unwrap();
-            let image = Image::from_dynamic(sprite_sheet, true);
-            let handle =
-                load_context.set_labeled_asset("spritesheet_image", LoadedAsset::new(image));
+            let ParsedAnimation {
+                info,
+                spritesheet: sprite_sheet,
+            } = anim_file::parse(load_context.path(), bytes)?;
+
+            let mut dependencies = vec![];
+            let (handle, source) = match sprite_sheet {
+                ParsedSpritesheet::Embedded(image) => {
+                    let image = Image::from_dynamic(image, true);
+                    let handle = load_context
+                        .set_labeled_asset("spritesheet_image", LoadedAsset::new(image));
+                    (handle, None)
+                }
+                ParsedSpritesheet::External(path) => {
+                    let path = AssetPath::new(path, None);
+                    let handle: Handle<Image> = load_context.get_handle(path.clone());
+                    dependencies.push(path);
+                    (handle.clone(), Some(handle))
+                }
+            };
             let handle = load_context.set_labeled_asset(
                 "spritesheet",
                 LoadedAsset::new(TextureAtlas::from_grid(
                     handle,
-                    Vec2::new(anim.info.cell_width as _, anim.info.cell_height as _),
-                    anim.info.columns,
-                    anim.info.frame_count.div_ceil(anim.info.columns),
+                    Vec2::new(info.cell_width as _, info.cell_height as _),
+                    info.columns,
+                    info.frame_count.div_ceil(info.columns),
                     None,
                     None,
                 )),
@@ -51,123 +75,94 @@ impl AssetLoader for AnimationLoader {
             let anim = Animation {
                 spritesheet: Spritesheet {
                     image: handle,
-                    cell_width: anim.info.cell_width,
-                    cell_height: anim.info.cell_height,
-                    colums: anim.info.columns,
-                    frame_count: anim.info.frame_count,
+                    cell_width: info.cell_width,
+                    cell_height: info.cell_height,
+                    colums: info.columns,
+                    frame_count: info.frame_count,
+                    source,
                 },
-                frames: anim
-                    .info
+                frames: info
                     .frame_data
                     .into_iter()
                     .map(|fd| Frame {
                         duration: fd.delay,
                         offset: fd.origin,
-                        root_motion: fd.root_motion,
-                        hitboxes: fd.hitboxes,
+                        root_motion: crate::Vec2 {
+                            x: fd.root_motion.x.into(),
+                            y: fd.root_motion.y.into(),
+                        },
+                        hitboxes: frame_hitboxes(&fd.hitboxes, &info.hitboxes),
+                        events: fd.events,
+                        flags: fd.flags,
                     })
                     .collect(),
-                hitboxes: anim.info.hitboxes,
+                use_root_motion: info.use_root_motion,
+                playback: info.playback,
+                speed: info.speed.into(),
             };
 
-            load_context.set_default_asset(LoadedAsset::new(anim));
+            load_context.set_default_asset(LoadedAsset::new(anim).with_dependencies(dependencies));
 
             Ok(())
         })
     }
 
     fn extensions(&self) -> &[&str] {
-        &["anim"]
+        &anim_file::Encoding::EXTENSIONS
     }
 }
 
-#[derive(Serialize, Deserialize)]
-struct AnimationFileData {
-    #[serde(with = "seethe")]
-    spritesheet: Vec<u8>,
-    info: Info,
+#[derive(Clone, TypeUuid)]
+#[uuid = "5fe2f03e-3d6f-4ac5-95ec-132d62b816fd"]
+pub struct Animation {
+    pub spritesheet: Spritesheet,
+    frames: Vec<Frame>,
+    use_root_motion: bool,
+    playback: PlaybackMode,
+    speed: FixedPoint,
 }
 
-mod seethe {
-    use base64::Engine;
-    use serde::{de::Visitor, Deserializer, Serializer};
-
-    pub(super) fn serialize<S: Serializer>(bytes: &[u8], mut s: S) -> Result<S::Ok, S::Error> {
-        if s.is_human_readable() {
-            s.serialize_str(&base64::engine::general_purpose::STANDARD_NO_PAD.encode(bytes))
-        } else {
-            s.serialize_bytes(bytes)
-        }
+impl Animation {
+    /// The boxes of the first frame, for things like projectiles that keep theirs as they are.
+    pub fn first_hitboxes(&self, facing: FacingDirection) -> &[Hitbox2] {
+        self.frames.first().map_or(&[], |frame| frame.hitboxes(facing))
     }
 
-    pub(super) fn deserialize<'d, D: Deserializer<'d>>(mut d: D) -> Result<Vec<u8>, D::Error> {
-        struct V;
-
-        impl Visitor<'_> for V {
-            type Value = Vec<u8>;
-
-            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("data")
-            }
-
-            fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
-            where
-                E: serde::de::Error,
-            {
-                Ok(v)
-            }
-
-            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
-            where
-                E: serde::de::Error,
-            {
-                Ok(v.into())
-            }
-
-            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
-            where
-                E: serde::de::Error,
-            {
-                Ok(base64::engine::general_purpose::STANDARD_NO_PAD
-                    .decode(v)
-                    .unwrap())
-            }
+    /// How far the root moves when stepping from frame `from` to its neighbour `to`. Wrapping
+    /// around a loop first plays out the motion up to the end, then the motion from the start
+    /// to `to`, so a looping walk keeps going the same way.
+    fn root_motion_step(
+        &self,
+        from: usize,
+        to: usize,
+        backwards: bool,
+        facing: FacingDirection,
+    ) -> crate::Vec2 {
+        let zero = crate::Vec2 {
+            x: FixedPoint::ZERO,
+            y: FixedPoint::ZERO,
+        };
+        if !self.use_root_motion {
+            return zero;
         }
 
-        if d.is_human_readable() {
-            d.deserialize_str(V)
-        } else {
-            d.deserialize_byte_buf(V)
+        let rm = |frame: usize| self.frames[frame].root_motion;
+        let (first, last) = (0, self.frames.len() - 1);
+        let delta = match (backwards, to <= from) {
+            (false, true) => rm(last) - rm(from) + rm(to) - rm(first),
+            (true, false) => rm(first) - rm(from) + rm(to) - rm(last),
+            _ => rm(to) - rm(from),
+        };
+        match facing {
+            FacingDirection::Right => delta,
+            FacingDirection::Left => crate::Vec2 {
+                x: -delta.x,
+                y: delta.y,
+            },
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
-struct Info {
-    cell_width: usize,
-    cell_height: usize,
-    columns: usize,
-    frame_count: usize,
-    frame_data: Vec<FrameData>,
-    hitboxes: HashMap<usize, Hitbox>,
-}
-
-#[derive(Serialize, Deserialize)]
-struct FrameData {
-    delay: usize,
-    origin: Vec2,
-    root_motion: Vec2,
-    hitboxes: HashMap<usize, HitboxPos>,
-}
-
-#[derive(Clone, TypeUuid)]
-#[uuid = "5fe2f03e-3d6f-4ac5-95ec-132d62b816fd"]
-pub struct Animation {
-    pub spritesheet: Spritesheet,
-    frames: Vec<Frame>,
-    hitboxes: HashMap<usize, Hitbox>,
-}
-
 #[derive(Clone)]
 pub struct Spritesheet {
     pub image: Handle<TextureAtlas>,
@@ -175,38 +170,132 @@ pub struct Spritesheet {
     cell_height: usize,
     colums: usize,
     frame_count: usize,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct Hitbox {
-    id: usize,
-    #[serde(alias = "desc")]
-    tag: String,
-    is_hurtbox: bool,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-pub struct HitboxPos {
-    id: usize,
-    pos: Vec2,
-    size: Vec2,
-    enabled: bool,
+    /// The image file the sheet was loaded from, if it is not embedded in the animation.
+    source: Option<Handle<Image>>,
 }
 
 #[derive(Clone)]
 pub struct Frame {
     duration: usize,
     offset: Vec2,
-    root_motion: Vec2,
-    hitboxes: HashMap<usize, HitboxPos>,
+    /// How far the root is from where it was on the first frame.
+    root_motion: crate::Vec2,
+    /// Enabled boxes ordered by id, facing right and facing left.
+    hitboxes: [Vec<Hitbox2>; 2],
+    events: Vec<FrameEvent>,
+    flags: FrameFlags,
+}
+
+impl Frame {
+    fn hitboxes(&self, facing: FacingDirection) -> &[Hitbox2] {
+        match facing {
+            FacingDirection::Right => &self.hitboxes[0],
+            FacingDirection::Left => &self.hitboxes[1],
+        }
+    }
+}
+
+/// Converts the boxes of a frame to fixed point once at load, so that nothing is converted or
+/// allocated while playing the animation.
+fn frame_hitboxes(
+    positions: &HashMap<usize, HitboxPos>,
+    hitboxes: &HashMap<usize, Hitbox>,
+) -> [Vec<Hitbox2>; 2] {
+    let mut positions: Vec<_> = positions.values().filter(|hp| hp.enabled).collect();
+    positions.sort_by_key(|hp| hp.id);
+
+    let right: Vec<_> = positions
+        .into_iter()
+        .filter_map(|hp| {
+            let hb = hitboxes.get(&hp.id)?;
+            Some(Hitbox2 {
+                id: hp.id,
+                offset: crate::Vec2 {
+                    x: hp.pos.x.into(),
+                    y: hp.pos.y.into(),
+                },
+                size: crate::Vec2 {
+                    x: hp.size.x.into(),
+                    y: hp.size.y.into(),
+                },
+                tag: Tag::new(&hb.tag),
+                kind: hb.kind,
+                attack: hb.attack.as_deref().map(Tag::new),
+                priority: hb.priority,
+                entity: None,
+            })
+        })
+        .collect();
+    let left = right
+        .iter()
+        .map(|hb| Hitbox2 {
+            offset: crate::Vec2 {
+                x: -hb.offset.x - hb.size.x,
+                y: hb.offset.y,
+            },
+            ..*hb
+        })
+        .collect();
+
+    [right, left]
 }
 
 #[derive(Component)]
 pub struct Animator {
     pub animation: Handle<Animation>,
     pub just_changed_animation: bool,
-    pub last_frame_change: usize,
-    pub idle_after_animation: bool,
+    /// Set when an animation is started, so that `animator` fires the events of its first
+    /// frame. Reloads and other refreshes leave it alone.
+    pub entered_frame: bool,
+    /// Time spent on the current frame, in frames scaled by the playback speed.
+    pub progress: FixedPoint,
+    /// Overrides the playback mode of the animation file.
+    pub playback: Option<PlaybackMode>,
+    /// Overrides the playback speed of the animation file.
+    pub speed: Option<FixedPoint>,
+    /// Whether a ping-pong animation is currently playing backwards.
+    pub reversed: bool,
+    /// Started once the current animation stops on its last frame, used to follow transition
+    /// animations with the state's own one.
+    pub queued: Option<Handle<Animation>>,
+    /// Frames entered during the current tick, kept between ticks so `animator` doesn't
+    /// allocate.
+    entered: Vec<usize>,
+}
+
+impl Animator {
+    pub fn new(animation: Handle<Animation>) -> Self {
+        Self {
+            animation,
+            just_changed_animation: true,
+            entered_frame: true,
+            progress: FixedPoint::ZERO,
+            playback: None,
+            speed: None,
+            reversed: false,
+            queued: None,
+            entered: vec![],
+        }
+    }
+
+    /// Starts `animation` from `frame`, with `playback` and `speed` overriding the animation
+    /// file when set.
+    pub fn play(
+        &mut self,
+        sprite: &mut TextureAtlasSprite,
+        animation: Handle<Animation>,
+        frame: usize,
+        playback: Option<PlaybackMode>,
+        speed: Option<FixedPoint>,
+    ) {
+        *self = Self {
+            playback,
+            speed,
+            entered: std::mem::take(&mut self.entered),
+            ..Self::new(animation)
+        };
+        sprite.index = frame;
+    }
 }
 
 #[derive(Component, Debug)]
@@ -214,24 +303,67 @@ pub struct Hitboxes {
     pub hitboxes: Vec<Hitbox2>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Hitbox2 {
+    pub id: usize,
     pub offset: crate::Vec2,
     pub size: crate::Vec2,
-    pub tag: String,
-    pub hitbox_type: HitboxType,
+    pub tag: Tag,
+    pub kind: HitboxKind,
+    pub attack: Option<Tag>,
+    pub priority: i32,
     entity: Option<Entity>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum HitboxType {
-    Hurtbox,
-    Hitbox
+/// Sent by `animator` for each event of a frame when the frame is entered.
+pub struct AnimationEvent {
+    pub entity: Entity,
+    pub event: FrameEvent,
+}
+
+/// Refreshes sprites and hitboxes of everything playing an animation that was edited on disk.
+fn reload_animations(
+    mut events: EventReader<AssetEvent<Animation>>,
+    mut query: Query<&mut Animator>,
+) {
+    for event in events.iter() {
+        let AssetEvent::Modified { handle } = event else {
+            continue;
+        };
+        info!("Reloading animation {:?}", handle);
+
+        for mut animator in query.iter_mut().filter(|a| a.animation == *handle) {
+            animator.just_changed_animation = true;
+        }
+    }
+}
+
+/// Reloads every animation whose external spritesheet was edited on disk.
+fn reload_spritesheets(
+    mut events: EventReader<AssetEvent<Image>>,
+    animations: Res<Assets<Animation>>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in events.iter() {
+        let AssetEvent::Modified { handle } = event else {
+            continue;
+        };
+
+        for (id, _) in animations
+            .iter()
+            .filter(|(_, anim)| anim.spritesheet.source.as_ref() == Some(handle))
+        {
+            if let Some(path) = asset_server.get_handle_path(id) {
+                asset_server.reload_asset(path);
+            }
+        }
+    }
 }
 
 pub(crate) fn animator(
     frame_ticker: Res<Frameticker>,
     mut query: Query<(
+        Entity,
         &mut Character,
         &mut Position,
         &mut Animator,
@@ -240,43 +372,144 @@ pub(crate) fn animator(
         &mut Hitboxes,
     )>,
     animations: Res<Assets<Animation>>,
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
+    stage: Res<Stage>,
+    rules: Res<Rules>,
+    mut events: EventWriter<AnimationEvent>,
+    mut whiffs: EventWriter<WhiffEvent>,
 ) {
-    for (mut character, mut pos, mut anim, mut sprite, mut atlas, mut hitboxes) in query.iter_mut()
+    let matrix = &rules.collision_matrix;
+    let obstacles = PushObstacles::new(
+        matrix,
+        query.iter().map(|(entity, _, pos, .., hitboxes)| (entity, pos, hitboxes)),
+    );
+
+    for (entity, mut character, mut pos, mut anim, mut sprite, mut atlas, mut hitboxes) in
+        query.iter_mut()
     {
         let flip = character.facing ==  FacingDirection::Left;
         let modifier = if flip { -1.0 } else { 1.0 };
         let modifier_vec = Vec2::new(modifier, 1.0);
         sprite.flip_x = flip;
 
-        let mut diff = frame_ticker.current_frame - anim.last_frame_change;
-        let cur_frame = sprite.index;
-
         let mut just_changed = false;
-        let last_frame = cur_frame;
 
-        let animation = animations.get(&anim.animation).unwrap();
+        let Some(animation) = animations.get(&anim.animation) else {
+            continue;
+        };
+        if animation.frames.is_empty() {
+            continue;
+        }
 
         *atlas = animation.spritesheet.image.clone();
 
-        while diff >= animation.frames[sprite.index].duration {
-            sprite.index += 1;
-            if sprite.index >= animation.frames.len() {
-                if anim.idle_after_animation {
-                    sprite.index = animation.frames.len() - 1;
-                    character.state = CharacterState::Idle;
-                    character.just_transitioned = true;
-                    character.current_move_on_hit = None;
-                    println!("{}: Player returned to normal from move", frame_ticker.current_frame + 1);
-                    return;
-                }
-                sprite.index = 0;
+        // The animation may have been reloaded with fewer frames since the index was set.
+        if sprite.index >= animation.frames.len() {
+            sprite.index = 0;
+            anim.progress = FixedPoint::ZERO;
+            anim.just_changed_animation = true;
+        }
+
+        let mut entered = std::mem::take(&mut anim.entered);
+        entered.clear();
+        if anim.entered_frame {
+            anim.entered_frame = false;
+            entered.push(sprite.index);
+        }
+
+        let playback = anim.playback.unwrap_or(animation.playback);
+        let last = animation.frames.len() - 1;
+        let mut returned = false;
+        let mut queued = None;
+        loop {
+            let duration = FixedPoint::from(animation.frames[sprite.index].duration);
+            if anim.progress < duration {
+                break;
             }
-            anim.last_frame_change = frame_ticker.current_frame;
-            just_changed = true;
 
-            diff = frame_ticker.current_frame - anim.last_frame_change;
+            let previous = sprite.index;
+            let next = match playback {
+                PlaybackMode::PingPong if last == 0 => None,
+                PlaybackMode::PingPong if anim.reversed && previous == 0 => {
+                    anim.reversed = false;
+                    Some(1)
+                }
+                PlaybackMode::PingPong if anim.reversed => Some(previous - 1),
+                PlaybackMode::PingPong if previous == last => {
+                    anim.reversed = true;
+                    Some(last - 1)
+                }
+                _ if previous < last => Some(previous + 1),
+                PlaybackMode::Loop => Some(0),
+                PlaybackMode::LoopFrom(frame) => Some(frame.min(last)),
+                PlaybackMode::OnceThenHold | PlaybackMode::PingPong => None,
+                PlaybackMode::OnceThenReturn => {
+                    returned = true;
+                    None
+                }
+            };
+            let Some(next) = next else {
+                queued = anim.queued.take();
+                // Holding the last frame, keep the time from growing without bound.
+                anim.progress = duration;
+                break;
+            };
+
+            sprite.index = next;
+            anim.progress -= duration;
+
+            // Root motion stops against other bodies and the walls, like walking does.
+            let mut root_motion = animation.root_motion_step(
+                previous,
+                sprite.index,
+                anim.reversed,
+                character.facing,
+            );
+            let boxes = animation.frames[sprite.index].hitboxes(character.facing);
+            root_motion.x = obstacles.limit(matrix, entity, &pos, boxes, root_motion.x);
+            pos.0 = pos.0 + root_motion;
+            pos.0.x = stage.clamp(pos.0.x, matrix.wall_span(boxes));
+            entered.push(sprite.index);
+
+            just_changed = true;
+        }
+        let speed = anim.speed.unwrap_or(animation.speed);
+        anim.progress += speed;
+
+        // Every frame passed through fires its events once, in order, even when the loop
+        // above skipped past it.
+        for &frame in &entered {
+            events.send_batch(animation.frames[frame].events.iter().map(|event| AnimationEvent {
+                entity,
+                event: event.clone(),
+            }));
+        }
+        anim.entered = entered;
+
+        if returned {
+            character.state = CharacterState::Idle;
+            character.just_transitioned = true;
+            let move_name = character.current_move.take();
+            let hit = std::mem::take(&mut character.current_move_hit);
+            character.current_move_id = None;
+            character.current_move_on_hit = None;
+            character.current_move_attacks.clear();
+            character.current_move_contacts.clear();
+            character.armor = 0;
+            // Only a move that ends without ever hitting whiffed. Animations played outside of
+            // moves can end this way too, and aren't attacks.
+            if move_name.is_some() && !hit {
+                whiffs.send(WhiffEvent {
+                    frame: frame_ticker.current_frame,
+                    attacker: entity,
+                    defender: None,
+                    move_name,
+                });
+            }
+            continue;
+        }
+        if let Some(queued) = queued {
+            anim.play(&mut sprite, queued, 0, None, None);
+            continue;
         }
 
         if just_changed || anim.just_changed_animation {
@@ -294,87 +527,105 @@ pub(crate) fn animator(
                     * Vec2::new(modifier, -1.0),
             );
 
-            // for hb in &hitboxes.hitboxes {
-            //     // println!("Despawning");
-            //     commands.get_entity(hb.entity.unwrap()).unwrap().despawn();
-            // }
-
+            character.frame_flags = animation.frames[cur_frame].flags;
             hitboxes.hitboxes.clear();
+            hitboxes
+                .hitboxes
+                .extend_from_slice(animation.frames[cur_frame].hitboxes(character.facing));
+        }
+    }
+}
 
-            for hp in animation.frames[cur_frame].hitboxes.values() {
-                let hb = &animation.hitboxes[&hp.id];
-
-                if hp.enabled {
-                    // let e = commands
-                    //     .spawn(SpriteBundle {
-                    //         texture: asset_server.load("pixel.png"),
-                    //         transform: Transform {
-                    //             translation: Vec3 {
-                    //                 x: f32::from(pos.0.x) + if flip { -hp.pos.x - hp.size.x / 2.0 } else { hp.pos.x + hp.size.x / 2.0 },
-                    //                 y: f32::from(pos.0.y) + hp.pos.y - hp.size.y / 2.0,
-                    //                 z: 0.0,
-                    //             },
-                    //             scale: Vec3 {
-                    //                 x: hp.size.x,
-                    //                 y: hp.size.y,
-                    //                 z: 1.0,
-                    //             },
-                    //             ..default()
-                    //         },
-                    //         ..default()
-                    //     })
-                    //     .id();
-
-                    hitboxes.hitboxes.push(Hitbox2 {
-                        offset: crate::Vec2 {
-                            x: if flip { -hp.pos.x - hp.size.x } else { hp.pos.x }.into(),
-                            y: hp.pos.y.into(),
-                        },
-                        size: crate::Vec2 {
-                            x: hp.size.x.into(),
-                            y: hp.size.y.into(),
-                        },
-                        tag: hb.tag.clone(),
-                        hitbox_type: if hb.is_hurtbox {
-                            HitboxType::Hurtbox
-                        } else {
-                            HitboxType::Hitbox
-                        },
-                        entity: None,
-                    })
-                }
+/// Applies the frame events that act on the character itself. The rest are left for whoever
+/// reads `AnimationEvent`.
+pub(crate) fn apply_frame_events(
+    mut events: EventReader<AnimationEvent>,
+    mut query: Query<(&mut Character, &mut crate::Velocity)>,
+) {
+    for AnimationEvent { entity, event } in events.iter() {
+        let Ok((mut character, mut velocity)) = query.get_mut(*entity) else {
+            continue;
+        };
+        let facing = if character.facing == FacingDirection::Right {
+            FixedPoint::from(1.0)
+        } else {
+            FixedPoint::from(-1.0)
+        };
+        let relative = |v: Vec2| crate::Vec2 {
+            x: FixedPoint::from(v.x) * facing,
+            y: FixedPoint::from(v.y),
+        };
+
+        match event {
+            FrameEvent::SetVelocity(v) => velocity.0 = relative(*v),
+            FrameEvent::AddVelocity(v) => velocity.0 = velocity.0 + relative(*v),
+            FrameEvent::Invulnerable(frames) => {
+                character.invulnerable = character.invulnerable.max(*frames)
             }
+            // Projectiles are spawned by `projectile::spawn_projectiles`.
+            FrameEvent::SpawnProjectile(_) | FrameEvent::PlaySound(_) | FrameEvent::Tag(_) => {}
+        }
+    }
+}
 
-            // println!("Updating hitboxes:");
-            // println!("{hitboxes:?}");
-
-            // println!("{:?}", sprite.anchor);
-            // if just_changed {
-            //     // let root_motion = animation.frames[cur_frame].root_motion;
-
-            //     // let root_motion = crate::Vec2 {
-            //     //     x: root_motion.x.into(),
-            //     //     y: root_motion.y.into(),
-            //     // };
-
-            //     // let last_root_motion = animation.frames[last_frame].root_motion;
-
-            //     // let last_root_motion = crate::Vec2 {
-            //     //     x: last_root_motion.x.into(),
-            //     //     y: last_root_motion.y.into(),
-            //     // };
-
-            //     // pos.0 = pos.0 + root_motion;
-            //     // if last_frame < cur_frame {
-            //     //     pos.0 = pos.0 - last_root_motion;
-            //     // }
-            //     if pos.0.x > FixedPoint::from(200.0) {
-            //         pos.0.x = FixedPoint::from(-200.0);
-            //     }
-            //     if pos.0.x < FixedPoint::from(-200.0) {
-            //         pos.0.x = FixedPoint::from(200.0);
-            //     }
-            // }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A walk whose root is at `xs` on each frame, like the running totals in `.anim` files.
+    fn walk(xs: &[f32]) -> Animation {
+        let frames = xs
+            .iter()
+            .map(|&x| Frame {
+                duration: 1,
+                offset: Vec2::ZERO,
+                root_motion: crate::Vec2 {
+                    x: FixedPoint::from(x),
+                    y: FixedPoint::ZERO,
+                },
+                hitboxes: [vec![], vec![]],
+                events: vec![],
+                flags: FrameFlags::default(),
+            })
+            .collect();
+        Animation {
+            spritesheet: Spritesheet {
+                image: Handle::default(),
+                cell_width: 1,
+                cell_height: 1,
+                colums: 1,
+                frame_count: xs.len(),
+                source: None,
+            },
+            frames,
+            use_root_motion: true,
+            playback: PlaybackMode::Loop,
+            speed: FixedPoint::from(1.0),
         }
     }
+
+    fn step_x(animation: &Animation, from: usize, to: usize, backwards: bool) -> f32 {
+        animation.root_motion_step(from, to, backwards, FacingDirection::Right).x.into()
+    }
+
+    #[test]
+    fn looping_walk_moves_its_length_each_loop() {
+        let animation = walk(&[0.0, 4.0, 7.0, 10.0, 11.0, 13.0, 16.0, 20.0]);
+        let steps: Vec<f32> = (0..8).map(|i| step_x(&animation, i, (i + 1) % 8, false)).collect();
+        assert_eq!(steps, [4.0, 3.0, 3.0, 1.0, 2.0, 3.0, 4.0, 0.0]);
+        assert_eq!(steps.iter().sum::<f32>(), 20.0);
+
+        let mut looped = walk(&[0.0, 4.0, 7.0, 10.0]);
+        looped.playback = PlaybackMode::LoopFrom(1);
+        assert_eq!(step_x(&looped, 3, 1, false), 4.0);
+    }
+
+    #[test]
+    fn stepping_backwards_takes_back_the_motion() {
+        let animation = walk(&[0.0, 4.0, 7.0, 10.0]);
+        assert_eq!(step_x(&animation, 2, 1, true), -3.0);
+        assert_eq!(step_x(&animation, 3, 2, true), -3.0);
+        let left = animation.root_motion_step(1, 2, false, FacingDirection::Left);
+        assert_eq!(f32::from(left.x), -3.0);
+    }
 }
@@ -0,0 +1,77 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::movelist::{StateContext, StateMatcher};
+
+/// Decides which animation a character plays outside of moves. Animations are referred to by
+/// their name in the character's animation table.
+#[derive(Component, Clone, Serialize, Deserialize)]
+pub struct AnimationGraph {
+    /// Checked in order, the first entry matching the current state is played.
+    pub states: Vec<StateAnimation>,
+    /// Played once before the state's own animation when switching between matching states.
+    #[serde(default)]
+    pub transitions: Vec<AnimationTransition>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StateAnimation {
+    pub when: StateMatcher,
+    pub animation: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AnimationTransition {
+    #[serde(default = "any_state")]
+    pub from: StateMatcher,
+    #[serde(default = "any_state")]
+    pub to: StateMatcher,
+    /// Applies when the character turned around instead of when its state changed, for
+    /// turnaround animations.
+    #[serde(default)]
+    pub turn: bool,
+    pub animation: String,
+}
+
+fn any_state() -> StateMatcher {
+    StateMatcher::All(vec![])
+}
+
+/// The animations to play after a state change.
+pub struct AnimationChoice<'a> {
+    pub transition: Option<&'a str>,
+    pub animation: &'a str,
+}
+
+impl AnimationGraph {
+    /// Every animation name the graph refers to.
+    pub fn animation_names(&self) -> impl Iterator<Item = &str> {
+        self.states
+            .iter()
+            .map(|s| s.animation.as_str())
+            .chain(self.transitions.iter().map(|t| t.animation.as_str()))
+    }
+
+    /// Picks the animations for going from `from` to `to`, or `None` if no state entry
+    /// matches `to`.
+    pub fn choose(
+        &self,
+        from: &StateContext,
+        to: &StateContext,
+        turned: bool,
+    ) -> Option<AnimationChoice<'_>> {
+        let animation = self.states.iter().find(|s| s.when.matches(to))?;
+        let transition = self.transition(from, to, turned);
+        Some(AnimationChoice {
+            transition,
+            animation: &animation.animation,
+        })
+    }
+
+    pub fn transition(&self, from: &StateContext, to: &StateContext, turned: bool) -> Option<&str> {
+        self.transitions
+            .iter()
+            .find(|t| t.turn == turned && t.from.matches(from) && t.to.matches(to))
+            .map(|t| t.animation.as_str())
+    }
+}
@@ -0,0 +1,474 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use bevy::math::Vec2;
+use fg::anim_file::{
+    self, AnimationFileData, Encoding, FrameData, Hitbox, HitboxKind, Info, ParsedAnimation,
+    ParsedSpritesheet, SpritesheetSource,
+};
+use image::{DynamicImage, GenericImage, GenericImageView};
+use serde::{Deserialize, Serialize};
+
+mod preview;
+
+const USAGE: &str = "\
+usage:
+    fg-anim inspect <file>
+    fg-anim extract <file> <out.png>
+    fg-anim unpack <file> <dir>
+    fg-anim pack <dir> <out.anim|out.animb>
+    fg-anim convert <from> <to>
+    fg-anim upgrade <file>...
+    fg-anim lint <file or dir>...
+    fg-anim preview <file> <out.png|out.gif> [--left] [--scale <n>]
+
+pack reads <dir>/manifest.json, or <dir>/manifest.toml when there is no JSON manifest.";
+
+/// Written by `unpack`. `pack` also accepts a TOML manifest when there is no JSON one.
+const MANIFEST: &str = "manifest.json";
+const TOML_MANIFEST: &str = "manifest.toml";
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let result = match args.as_slice() {
+        ["inspect", file] => inspect(Path::new(file)),
+        ["extract", file, out] => extract(Path::new(file), Path::new(out)),
+        ["unpack", file, dir] => unpack(Path::new(file), Path::new(dir)),
+        ["pack", dir, out] => pack(Path::new(dir), Path::new(out)),
+        ["convert", from, to] => {
+            anim_file::convert(Path::new(from), Path::new(to)).map_err(Into::into)
+        }
+        ["upgrade", files @ ..] if !files.is_empty() => upgrade(files),
+        ["lint", paths @ ..] if !paths.is_empty() => lint(paths),
+        ["preview", file, out, options @ ..] => match preview_options(options) {
+            Some((flip, scale)) => preview(Path::new(file), Path::new(out), flip, scale),
+            None => {
+                eprintln!("{USAGE}");
+                return ExitCode::FAILURE;
+            }
+        },
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The hand-editable description of an unpacked animation, stored next to its frame images.
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    columns: usize,
+    frames: Vec<ManifestFrame>,
+    hitboxes: HashMap<usize, Hitbox>,
+    #[serde(default)]
+    use_root_motion: bool,
+    #[serde(default)]
+    playback: anim_file::PlaybackMode,
+    #[serde(default = "one")]
+    speed: f32,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+#[derive(Serialize, Deserialize)]
+struct ManifestFrame {
+    /// Path of the frame image, relative to the manifest.
+    image: String,
+    delay: usize,
+    origin: Vec2,
+    root_motion: Vec2,
+    hitboxes: HashMap<usize, anim_file::HitboxPos>,
+    #[serde(default)]
+    events: Vec<anim_file::FrameEvent>,
+    #[serde(default)]
+    flags: anim_file::FrameFlags,
+}
+
+fn load(path: &Path) -> std::result::Result<ParsedAnimation, anim_file::AnimationError> {
+    let bytes = std::fs::read(path).map_err(|e| anim_file::AnimationError {
+        path: path.to_path_buf(),
+        kind: anim_file::AnimationErrorKind::Io(e),
+    })?;
+    anim_file::parse(path, &bytes)
+}
+
+fn load_spritesheet(spritesheet: ParsedSpritesheet) -> Result<DynamicImage> {
+    match spritesheet {
+        ParsedSpritesheet::Embedded(image) => Ok(image),
+        ParsedSpritesheet::External(path) => Ok(image::open(path)?),
+    }
+}
+
+fn sorted<T>(map: &HashMap<usize, T>) -> Vec<(&usize, &T)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by_key(|(id, _)| **id);
+    entries
+}
+
+fn inspect(path: &Path) -> Result<()> {
+    let ParsedAnimation { info, spritesheet } = load(path)?;
+
+    println!("{}", path.display());
+    match &spritesheet {
+        ParsedSpritesheet::Embedded(image) => {
+            println!(
+                "spritesheet: embedded, {}x{}",
+                image.width(),
+                image.height()
+            )
+        }
+        ParsedSpritesheet::External(image) => println!("spritesheet: {}", image.display()),
+    }
+    println!(
+        "cells: {}x{}, {} columns, {} frames, {} ticks",
+        info.cell_width,
+        info.cell_height,
+        info.columns,
+        info.frame_count,
+        info.frame_data.iter().map(|fd| fd.delay).sum::<usize>()
+    );
+    println!("playback: {:?} at {}x speed", info.playback, info.speed);
+    if info.use_root_motion {
+        println!("root motion: applied");
+    }
+
+    println!("hitboxes:");
+    for (id, hitbox) in sorted(&info.hitboxes) {
+        let kind = format!("{:?}", hitbox.kind).to_lowercase();
+        print!("  {id:>3}  {kind:<9}  {}", hitbox.tag);
+        if let Some(attack) = &hitbox.attack {
+            print!("  attack {attack}");
+        }
+        if hitbox.priority != 0 {
+            print!("  priority {}", hitbox.priority);
+        }
+        println!();
+    }
+
+    println!("frames:");
+    for (i, fd) in info.frame_data.iter().enumerate() {
+        println!(
+            "  {i:>3}  delay {:>2}  origin ({}, {})  root motion ({}, {})",
+            fd.delay, fd.origin.x, fd.origin.y, fd.root_motion.x, fd.root_motion.y
+        );
+        for (_, hp) in sorted(&fd.hitboxes) {
+            println!(
+                "         {:>3}  pos ({}, {})  size ({}, {}){}",
+                hp.id,
+                hp.pos.x,
+                hp.pos.y,
+                hp.size.x,
+                hp.size.y,
+                if hp.enabled { "" } else { "  disabled" }
+            );
+        }
+        for event in &fd.events {
+            println!("         event {event:?}");
+        }
+        if fd.flags != anim_file::FrameFlags::default() {
+            println!("         flags {:?}", fd.flags);
+        }
+    }
+
+    Ok(())
+}
+
+fn extract(path: &Path, out: &Path) -> Result<()> {
+    let ParsedAnimation { spritesheet, .. } = load(path)?;
+    load_spritesheet(spritesheet)?.save(out)?;
+    Ok(())
+}
+
+fn cell(info: &Info, index: usize) -> (u32, u32) {
+    (
+        ((index % info.columns) * info.cell_width) as u32,
+        ((index / info.columns) * info.cell_height) as u32,
+    )
+}
+
+fn unpack(path: &Path, dir: &Path) -> Result<()> {
+    let ParsedAnimation {
+        mut info,
+        spritesheet,
+    } = load(path)?;
+    let sheet = load_spritesheet(spritesheet)?;
+    std::fs::create_dir_all(dir)?;
+
+    let mut frames = vec![];
+    for (i, fd) in std::mem::take(&mut info.frame_data).into_iter().enumerate() {
+        let image = format!("frame_{i:03}.png");
+        let (x, y) = cell(&info, i);
+        sheet
+            .crop_imm(x, y, info.cell_width as u32, info.cell_height as u32)
+            .save(dir.join(&image))?;
+
+        frames.push(ManifestFrame {
+            image,
+            delay: fd.delay,
+            origin: fd.origin,
+            root_motion: fd.root_motion,
+            hitboxes: fd.hitboxes,
+            events: fd.events,
+            flags: fd.flags,
+        });
+    }
+
+    let manifest = Manifest {
+        columns: info.columns,
+        frames,
+        hitboxes: info.hitboxes,
+        use_root_motion: info.use_root_motion,
+        playback: info.playback,
+        speed: info.speed,
+    };
+    std::fs::write(dir.join(MANIFEST), serde_json::to_vec_pretty(&manifest)?)?;
+    Ok(())
+}
+
+fn read_manifest(dir: &Path) -> Result<Manifest> {
+    let json = dir.join(MANIFEST);
+    if json.exists() {
+        return Ok(serde_json::from_slice(&std::fs::read(json)?)?);
+    }
+    // Going through JSON lets the hitbox tables keep their numeric keys, which TOML only
+    // allows as strings.
+    let toml: toml::Value = toml::from_str(&std::fs::read_to_string(dir.join(TOML_MANIFEST))?)?;
+    Ok(serde_json::from_value(serde_json::to_value(toml)?)?)
+}
+
+fn pack(dir: &Path, out: &Path) -> Result<()> {
+    let manifest = read_manifest(dir)?;
+    if manifest.columns == 0 {
+        return Err("manifest has 0 columns".into());
+    }
+
+    let images = manifest
+        .frames
+        .iter()
+        .map(|frame| image::open(dir.join(&frame.image)))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let Some(first) = images.first() else {
+        return Err("manifest has no frames".into());
+    };
+    let (width, height) = first.dimensions();
+    if let Some((frame, image)) = manifest
+        .frames
+        .iter()
+        .zip(&images)
+        .find(|(_, image)| image.dimensions() != (width, height))
+    {
+        let (w, h) = image.dimensions();
+        return Err(format!(
+            "{} is {w}x{h}, but frames are {width}x{height}",
+            frame.image
+        )
+        .into());
+    }
+
+    let mut info = Info {
+        cell_width: width as usize,
+        cell_height: height as usize,
+        columns: manifest.columns.min(images.len()),
+        frame_count: images.len(),
+        frame_data: vec![],
+        hitboxes: manifest.hitboxes,
+        use_root_motion: manifest.use_root_motion,
+        playback: manifest.playback,
+        speed: manifest.speed,
+    };
+
+    let rows = images.len().div_ceil(info.columns);
+    let mut sheet = DynamicImage::new_rgba8(width * info.columns as u32, height * rows as u32);
+    for (i, image) in images.iter().enumerate() {
+        let (x, y) = cell(&info, i);
+        sheet.copy_from(image, x, y)?;
+    }
+
+    info.frame_data = manifest
+        .frames
+        .into_iter()
+        .map(|frame| FrameData {
+            delay: frame.delay,
+            origin: frame.origin,
+            root_motion: frame.root_motion,
+            hitboxes: frame.hitboxes,
+            events: frame.events,
+            flags: frame.flags,
+        })
+        .collect();
+
+    let mut png = std::io::Cursor::new(vec![]);
+    sheet.write_to(&mut png, image::ImageOutputFormat::Png)?;
+    let file = AnimationFileData {
+        version: anim_file::CURRENT_VERSION,
+        spritesheet: SpritesheetSource::Embedded(anim_file::seethe::EncodedBytes::Raw(
+            png.into_inner(),
+        )),
+        info,
+    };
+
+    let encoding = Encoding::from_path(out).unwrap_or(Encoding::Json);
+    let bytes = file
+        .write(encoding)
+        .map_err(|kind| anim_file::AnimationError {
+            path: out.to_path_buf(),
+            kind,
+        })?;
+    // Round trip through the loader so that a bad manifest is caught here rather than in game.
+    anim_file::parse(out, &bytes)?;
+    std::fs::write(out, bytes)?;
+    Ok(())
+}
+
+fn upgrade(files: &[&str]) -> Result<()> {
+    for file in files {
+        if anim_file::upgrade_file(Path::new(file))? {
+            println!("upgraded {file}");
+        }
+    }
+    Ok(())
+}
+
+/// Problems that still load, but are likely mistakes.
+fn lints(info: &Info) -> Vec<String> {
+    let mut warnings = vec![];
+    let used_hurtbox = |fd: &FrameData| {
+        fd.hitboxes.values().any(|hp| {
+            hp.enabled
+                && info
+                    .hitboxes
+                    .get(&hp.id)
+                    .is_some_and(|hb| hb.kind == HitboxKind::Hurtbox)
+        })
+    };
+
+    for (i, fd) in info.frame_data.iter().enumerate() {
+        if !used_hurtbox(fd) {
+            warnings.push(format!("frame {i} has no enabled hurtbox"));
+        }
+
+        for (_, hp) in sorted(&fd.hitboxes) {
+            if hp.size.x <= 0.0 || hp.size.y <= 0.0 {
+                warnings.push(format!("frame {i}: hitbox {} has no area", hp.id));
+            }
+
+            // Proximity boxes cover the space in front of the attack, not the sprite.
+            let proximity = info
+                .hitboxes
+                .get(&hp.id)
+                .is_some_and(|hb| hb.kind == HitboxKind::Proximity);
+
+            // Hitboxes are placed relative to the origin with y pointing up, the cell has y
+            // pointing down.
+            let left = fd.origin.x + hp.pos.x;
+            let top = fd.origin.y - hp.pos.y;
+            let outside = left < 0.0
+                || top < 0.0
+                || left + hp.size.x > info.cell_width as f32
+                || top + hp.size.y > info.cell_height as f32;
+            if outside && !proximity {
+                warnings.push(format!(
+                    "frame {i}: hitbox {} reaches outside the cell",
+                    hp.id
+                ));
+            }
+        }
+    }
+
+    for (id, hitbox) in sorted(&info.hitboxes) {
+        if !info
+            .frame_data
+            .iter()
+            .any(|fd| fd.hitboxes.contains_key(id))
+        {
+            warnings.push(format!(
+                "hitbox {id} ({}) is not used by any frame",
+                hitbox.tag
+            ));
+        }
+    }
+
+    warnings
+}
+
+fn lint_paths(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if path.is_dir() {
+        let mut entries = std::fs::read_dir(path)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        entries.sort();
+        for entry in entries {
+            if entry.is_dir() || Encoding::from_path(&entry).is_some() {
+                lint_paths(&entry, files)?;
+            }
+        }
+    } else {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+fn lint(paths: &[&str]) -> Result<()> {
+    let mut files = vec![];
+    for path in paths {
+        lint_paths(Path::new(path), &mut files)?;
+    }
+
+    let mut problems = 0;
+    for file in files {
+        match load(&file) {
+            Ok(parsed) => {
+                for warning in lints(&parsed.info) {
+                    println!("{}: {warning}", file.display());
+                    problems += 1;
+                }
+            }
+            Err(e) => {
+                println!("{e}");
+                problems += 1;
+            }
+        }
+    }
+
+    match problems {
+        0 => Ok(()),
+        n => Err(format!("found {n} problems").into()),
+    }
+}
+
+fn preview_options(options: &[&str]) -> Option<(bool, u32)> {
+    let mut flip = false;
+    let mut scale = 1;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match *option {
+            "--left" => flip = true,
+            "--scale" => scale = options.next()?.parse().ok().filter(|&n| n > 0)?,
+            _ => return None,
+        }
+    }
+    Some((flip, scale))
+}
+
+fn preview(path: &Path, out: &Path, flip: bool, scale: u32) -> Result<()> {
+    let ParsedAnimation { info, spritesheet } = load(path)?;
+    let sheet = load_spritesheet(spritesheet)?;
+    preview::preview(&info, &sheet, out, flip, scale)
+}
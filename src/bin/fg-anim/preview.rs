@@ -0,0 +1,202 @@
+use std::{fs::File, path::Path};
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    imageops, Delay, DynamicImage, Frame, Rgba, RgbaImage,
+};
+
+use fg::anim_file::{HitboxKind, Info};
+
+const HURTBOX: Rgba<u8> = Rgba([0, 255, 0, 255]);
+const HITBOX: Rgba<u8> = Rgba([255, 0, 0, 255]);
+const THROWBOX: Rgba<u8> = Rgba([255, 0, 255, 255]);
+const THROWABLE: Rgba<u8> = Rgba([255, 255, 0, 255]);
+const PUSHBOX: Rgba<u8> = Rgba([0, 255, 255, 255]);
+const PROXIMITY: Rgba<u8> = Rgba([255, 128, 0, 255]);
+const COLLISION: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const ORIGIN: Rgba<u8> = Rgba([0, 128, 255, 255]);
+/// Arm length of the cross marking the origin.
+const MARKER_SIZE: i32 = 2;
+
+/// Ticks per second of the fixed update, used for GIF frame delays.
+const TICK_RATE: u32 = 60;
+
+/// A rectangle in pixels relative to the origin of a frame, with y pointing down.
+#[derive(Clone, Copy)]
+struct Rect {
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+}
+
+impl Rect {
+    fn mirrored(self, flip: bool) -> Rect {
+        if flip {
+            Rect {
+                x: -self.x - self.w,
+                ..self
+            }
+        } else {
+            self
+        }
+    }
+
+    fn union(self, other: Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect {
+            x,
+            y,
+            w: (self.x + self.w).max(other.x + other.w) - x,
+            h: (self.y + self.h).max(other.y + other.h) - y,
+        }
+    }
+}
+
+/// The sprite and enabled boxes of one frame, positioned relative to its origin.
+fn layout(info: &Info, index: usize, flip: bool) -> (Rect, Vec<(Rect, Option<HitboxKind>)>) {
+    let fd = &info.frame_data[index];
+    let sprite = Rect {
+        x: -fd.origin.x as i32,
+        y: -fd.origin.y as i32,
+        w: info.cell_width as i32,
+        h: info.cell_height as i32,
+    }
+    .mirrored(flip);
+
+    let mut boxes: Vec<_> = fd.hitboxes.values().filter(|hp| hp.enabled).collect();
+    boxes.sort_by_key(|hp| hp.id);
+    let boxes = boxes
+        .into_iter()
+        .map(|hp| {
+            // Boxes are stored with y pointing up, like the rest of the game.
+            let rect = Rect {
+                x: hp.pos.x as i32,
+                y: -hp.pos.y as i32,
+                w: hp.size.x as i32,
+                h: hp.size.y as i32,
+            };
+            let kind = info.hitboxes.get(&hp.id).map(|hb| hb.kind);
+            (rect.mirrored(flip), kind)
+        })
+        .collect();
+
+    (sprite, boxes)
+}
+
+fn put(image: &mut RgbaImage, x: i32, y: i32, colour: Rgba<u8>) {
+    if x >= 0 && y >= 0 && (x as u32) < image.width() && (y as u32) < image.height() {
+        image.put_pixel(x as u32, y as u32, colour);
+    }
+}
+
+fn outline(image: &mut RgbaImage, rect: Rect, colour: Rgba<u8>) {
+    for x in rect.x..rect.x + rect.w {
+        put(image, x, rect.y, colour);
+        put(image, x, rect.y + rect.h - 1, colour);
+    }
+    for y in rect.y..rect.y + rect.h {
+        put(image, rect.x, y, colour);
+        put(image, rect.x + rect.w - 1, y, colour);
+    }
+}
+
+/// Renders every frame on canvases of the same size, with the origin at the same spot in each
+/// so that the character stands still like it does in game.
+fn render_frames(info: &Info, sheet: &DynamicImage, flip: bool, scale: u32) -> Vec<RgbaImage> {
+    let layouts: Vec<_> = (0..info.frame_count)
+        .map(|i| layout(info, i, flip))
+        .collect();
+    let marker = Rect {
+        x: -MARKER_SIZE,
+        y: -MARKER_SIZE,
+        w: MARKER_SIZE * 2 + 1,
+        h: MARKER_SIZE * 2 + 1,
+    };
+    let bounds = layouts
+        .iter()
+        .flat_map(|(sprite, boxes)| std::iter::once(*sprite).chain(boxes.iter().map(|b| b.0)))
+        .fold(marker, Rect::union);
+
+    layouts
+        .into_iter()
+        .enumerate()
+        .map(|(i, (sprite, boxes))| {
+            let mut canvas = RgbaImage::new(bounds.w as u32, bounds.h as u32);
+            let to_canvas = |rect: Rect| Rect {
+                x: rect.x - bounds.x,
+                y: rect.y - bounds.y,
+                ..rect
+            };
+
+            let x = ((i % info.columns) * info.cell_width) as u32;
+            let y = ((i / info.columns) * info.cell_height) as u32;
+            let mut cell = sheet
+                .crop_imm(x, y, info.cell_width as u32, info.cell_height as u32)
+                .into_rgba8();
+            if flip {
+                imageops::flip_horizontal_in_place(&mut cell);
+            }
+            let sprite = to_canvas(sprite);
+            imageops::overlay(&mut canvas, &cell, sprite.x as i64, sprite.y as i64);
+
+            for (rect, kind) in boxes {
+                let colour = match kind {
+                    Some(HitboxKind::Hurtbox) => HURTBOX,
+                    Some(HitboxKind::Throwbox) => THROWBOX,
+                    Some(HitboxKind::Throwable) => THROWABLE,
+                    Some(HitboxKind::Pushbox) => PUSHBOX,
+                    Some(HitboxKind::Proximity) => PROXIMITY,
+                    Some(HitboxKind::Collision) => COLLISION,
+                    Some(HitboxKind::Hitbox) | None => HITBOX,
+                };
+                outline(&mut canvas, to_canvas(rect), colour);
+            }
+
+            for d in -MARKER_SIZE..=MARKER_SIZE {
+                put(&mut canvas, d - bounds.x, -bounds.y, ORIGIN);
+                put(&mut canvas, -bounds.x, d - bounds.y, ORIGIN);
+            }
+
+            imageops::resize(
+                &canvas,
+                canvas.width() * scale,
+                canvas.height() * scale,
+                imageops::FilterType::Nearest,
+            )
+        })
+        .collect()
+}
+
+/// Writes the frames side by side to a PNG, or as an animated GIF if `out` ends in `.gif`.
+pub fn preview(
+    info: &Info,
+    sheet: &DynamicImage,
+    out: &Path,
+    flip: bool,
+    scale: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let frames = render_frames(info, sheet, flip, scale);
+    let Some(first) = frames.first() else {
+        return Err("animation has no frames".into());
+    };
+
+    if out.extension().is_some_and(|ext| ext == "gif") {
+        let mut encoder = GifEncoder::new(File::create(out)?);
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames.into_iter().zip(&info.frame_data).map(|(image, fd)| {
+            let delay = Delay::from_numer_denom_ms(fd.delay as u32 * 1000, TICK_RATE);
+            Frame::from_parts(image, 0, 0, delay)
+        }))?;
+    } else {
+        let (width, height) = first.dimensions();
+        let mut strip = RgbaImage::new(width * frames.len() as u32, height);
+        for (i, frame) in frames.iter().enumerate() {
+            imageops::replace(&mut strip, frame, (i as u32 * width) as i64, 0);
+        }
+        strip.save(out)?;
+    }
+
+    Ok(())
+}
@@ -2,12 +2,19 @@ use std::collections::HashMap;
 
 use bevy::{ecs::schedule::SystemConfigs, prelude::*};
 use leafwing_input_manager::prelude::ActionState;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     animation::{Animation, Animator},
-    effects::Effect,
+    animation_graph::AnimationGraph,
+    effects::{AttackKind, Effect},
+    events::{CombatEvents, MoveStartedEvent},
     fixedpoint::FixedPoint,
-    movelist::{Button, Movelist},
+    anim_file::{FrameFlags, PlaybackMode},
+    movelist::{Button, Movelist, StateContext},
+    rules::Rules,
+    stage::WallReaction,
+    tag::Tag,
     AbsoluteDirection, Frameticker, Velocity, Position,
 };
 
@@ -16,10 +23,83 @@ pub struct Character {
     pub facing: FacingDirection,
     pub state: CharacterState,
     pub just_transitioned: bool,
-    pub animations: HashMap<&'static str, Handle<Animation>>,
-    pub new_anim: bool,
+    pub animations: HashMap<String, Handle<Animation>>,
+    /// State and facing the current animation was picked for by the animation graph.
+    pub animated_state: CharacterState,
+    pub animated_facing: FacingDirection,
     pub input_dir: crate::AbsoluteDirection,
+    pub current_move: Option<String>,
+    /// Index of the current move in the character's `Movelist`.
+    pub current_move_id: Option<usize>,
     pub current_move_on_hit: Option<Vec<Effect>>,
+    /// Effects of the current move's hitboxes that name an attack of their own.
+    pub current_move_attacks: HashMap<Tag, Vec<Effect>>,
+    /// Frame the current move started on.
+    pub move_started: usize,
+    pub current_move_hit: bool,
+    /// Boxes of the current move that already hit or clashed, with the opponent they met. They
+    /// stay spent while they keep overlapping that opponent, until another move starts.
+    pub current_move_contacts: Vec<(usize, Entity)>,
+    pub combo: Combo,
+    /// Position of the opponent whose active hitbox is within proximity guard range.
+    pub proximity_attack: Option<FixedPoint>,
+    /// Frames left during which hits are ignored.
+    pub invulnerable: usize,
+    /// Flags of the animation frame currently shown.
+    pub frame_flags: FrameFlags,
+    /// Hits the current move can still absorb without being interrupted.
+    pub armor: usize,
+}
+
+impl Character {
+    /// What move conditions and the animation graph see of the character outside of a move.
+    pub fn state_context(&self, position: &Position, meter_bars: usize) -> StateContext<'static> {
+        StateContext {
+            state: self.state,
+            airborne: position.0.y > FixedPoint::ZERO || self.frame_flags.airborne,
+            current_move: None,
+            move_frame: 0,
+            move_hit: false,
+            meter_bars,
+        }
+    }
+
+    /// Whether hits of this kind currently pass through the character.
+    pub fn is_invulnerable_to(&self, kind: AttackKind) -> bool {
+        self.invulnerable > 0
+            || match kind {
+                AttackKind::Strike => self.frame_flags.strike_invulnerable,
+                AttackKind::Throw => {
+                    self.frame_flags.throw_invulnerable || self.frame_flags.airborne
+                }
+                AttackKind::Projectile => self.frame_flags.projectile_invulnerable,
+            }
+    }
+
+    /// Whether the held direction guards against an attacker at `attacker_x`. This is judged
+    /// from where the attacker actually is rather than from `facing`, so cross-ups have to be
+    /// blocked the other way.
+    pub fn is_holding_guard(&self, x: FixedPoint, attacker_x: FixedPoint, rules: &Rules) -> bool {
+        let dx = attacker_x - x;
+        if dx.abs() <= rules.crossup_ambiguity {
+            self.input_dir.is_left() || self.input_dir.is_right()
+        } else if dx > FixedPoint::ZERO {
+            self.input_dir.is_left()
+        } else {
+            self.input_dir.is_right()
+        }
+    }
+}
+
+/// Bookkeeping for the combo this character is currently receiving, reset once they recover.
+#[derive(Default)]
+pub struct Combo {
+    pub juggle_points: usize,
+    pub wall_bounced: bool,
+    pub ground_bounced: bool,
+    pub wall_splatted: bool,
+    pub pending_wall_reaction: Option<WallReaction>,
+    pub pending_ground_bounce: Option<FixedPoint>,
 }
 
 #[derive(Component, PartialEq, Eq, Clone, Copy, Debug)]
@@ -42,16 +122,43 @@ pub enum FacingDirection {
     Right,
 }
 
-#[derive(Default, PartialEq, Eq, Clone, Copy)]
+#[derive(Default, PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum CharacterState {
     #[default]
     Idle,
     MovingForward,
     MovingBackward,
+    /// Holding back near an active attack, standing still instead of walking away.
+    Guarding,
+    Jumping,
     Normal,
 
     Hitstun(usize),
     Blockstun(usize),
+    /// Airborne after being hit; lasts until the character lands.
+    Launched,
+    /// Stuck to a wall after a wall splat, falling back into `Launched` once it runs out.
+    WallSplat(usize),
+    /// Guard was broken by blocking too much; unable to act or block until it runs out.
+    GuardCrush(usize),
+}
+
+impl CharacterState {
+    pub fn is_juggled(&self) -> bool {
+        matches!(self, CharacterState::Launched | CharacterState::WallSplat(_))
+    }
+
+    /// Whether a character in this state is able to block when holding back.
+    pub fn can_guard(&self) -> bool {
+        matches!(
+            self,
+            CharacterState::Idle
+                | CharacterState::MovingForward
+                | CharacterState::MovingBackward
+                | CharacterState::Guarding
+                | CharacterState::Blockstun(_)
+        )
+    }
 }
 
 #[derive(Component, Default)]
@@ -86,6 +193,52 @@ impl InputHistory {
 #[derive(Component)]
 pub struct Health {
     pub value: FixedPoint,
+    pub max: FixedPoint,
+}
+
+/// Movement properties that come from the character's definition file.
+#[derive(Component, Clone, Serialize, Deserialize)]
+pub struct Stats {
+    pub walk_forward_speed: FixedPoint,
+    pub walk_backward_speed: FixedPoint,
+    pub jump: JumpArc,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct JumpArc {
+    /// Horizontal speed of forward and backward jumps.
+    pub forward: FixedPoint,
+    /// Vertical speed at takeoff.
+    pub up: FixedPoint,
+    /// Vertical speed lost every frame while airborne, including when launched.
+    pub gravity: FixedPoint,
+}
+
+#[derive(Component)]
+pub struct Guard {
+    pub value: FixedPoint,
+}
+
+#[derive(Component)]
+pub struct Meter {
+    pub value: FixedPoint,
+}
+
+impl Meter {
+    pub fn bars(&self, rules: &Rules) -> usize {
+        if rules.meter_per_bar == FixedPoint::ZERO {
+            return 0;
+        }
+        (self.value / rules.meter_per_bar).floor() as usize
+    }
+
+    pub fn gain(&mut self, amount: FixedPoint, rules: &Rules) {
+        self.value = (self.value + amount).min(rules.max_meter());
+    }
+
+    pub fn spend(&mut self, bars: usize, rules: &Rules) {
+        self.value -= rules.meter_per_bar * FixedPoint::from(bars);
+    }
 }
 pub struct InputAction {
     pub time: usize,
@@ -117,8 +270,13 @@ pub(crate) fn input_manager(mut query: Query<(&mut Character, &ActionState<crate
 }
 
 pub(crate) fn facing_corrector(mut query: Query<(Entity, &Position, &mut Character, &Team)>) {
-    let (p1, p1pos, _, _) = query.iter().find(|(_, _, _, t)| **t == Team::Team1).unwrap();
-    let (p2, p2pos, _, _) = query.iter().find(|(_, _, _, t)| **t == Team::Team2).unwrap();
+    // Players are spawned once their character definitions have loaded.
+    let Some((p1, p1pos, _, _)) = query.iter().find(|(_, _, _, t)| **t == Team::Team1) else {
+        return;
+    };
+    let Some((p2, p2pos, _, _)) = query.iter().find(|(_, _, _, t)| **t == Team::Team2) else {
+        return;
+    };
 
     let p1pos = *p1pos;
     let p2pos = *p2pos;
@@ -133,24 +291,44 @@ pub(crate) fn facing_corrector(mut query: Query<(Entity, &Position, &mut Charact
 }
 
 pub(crate) fn state_manager(
-    frameticker: Res<Frameticker>,
+    mut frameticker: ResMut<Frameticker>,
+    rules: Res<Rules>,
     mut query: Query<(
+        Entity,
         &mut Character,
+        &Position,
         &mut Velocity,
+        &mut Meter,
+        &Stats,
         &Movelist,
         &InputHistory,
         &ActionState<crate::Input>,
+        &AnimationGraph,
         &mut Animator,
         &mut TextureAtlasSprite,
     )>,
+    mut events: CombatEvents,
 ) {
-    for (mut character, mut velocity, movelist, input_history, input, mut animator, mut sprite) in
-        query.iter_mut()
+    for (
+        entity,
+        mut character,
+        position,
+        mut velocity,
+        mut meter,
+        stats,
+        movelist,
+        input_history,
+        input,
+        graph,
+        mut animator,
+        mut sprite,
+    ) in query.iter_mut()
     {
+        character.invulnerable = character.invulnerable.saturating_sub(1);
         if let CharacterState::Hitstun(frames) = character.state {
             if frames == 0 {
                 character.state = CharacterState::Idle;
-                println!("{}: Player returned to normal after hitstun", frameticker.current_frame)
+                character.combo = Combo::default();
             } else {
                 character.state = CharacterState::Hitstun(frames - 1);
             }
@@ -158,13 +336,64 @@ pub(crate) fn state_manager(
         if let CharacterState::Blockstun(frames) = character.state {
             if frames == 0 {
                 character.state = CharacterState::Idle;
-                println!("{}: Player returned to normal after blockstun", frameticker.current_frame)
             } else {
                 character.state = CharacterState::Blockstun(frames - 1);
             }
         }
+        if let CharacterState::GuardCrush(frames) = character.state {
+            if frames == 0 {
+                character.state = CharacterState::Idle;
+                character.combo = Combo::default();
+            } else {
+                character.state = CharacterState::GuardCrush(frames - 1);
+            }
+        }
+        if let CharacterState::WallSplat(frames) = character.state {
+            if frames == 0 {
+                character.state = CharacterState::Launched;
+            } else {
+                character.state = CharacterState::WallSplat(frames - 1);
+            }
+        }
+
+        let proximity_guard = character
+            .proximity_attack
+            .is_some_and(|attacker_x| character.is_holding_guard(position.0.x, attacker_x, &rules));
 
         match character.state {
+            CharacterState::Idle
+            | CharacterState::MovingForward
+            | CharacterState::MovingBackward
+            | CharacterState::Guarding
+                if character.input_dir.is_up() =>
+            {
+                let direction = match character.input_dir.flipped(character.facing) {
+                    AbsoluteDirection::UpRight => FixedPoint::from(1.0),
+                    AbsoluteDirection::UpLeft => FixedPoint::from(-1.0),
+                    _ => FixedPoint::ZERO,
+                };
+                let facing = if character.facing == FacingDirection::Right {
+                    FixedPoint::from(1.0)
+                } else {
+                    FixedPoint::from(-1.0)
+                };
+                character.state = CharacterState::Jumping;
+                character.just_transitioned = true;
+                velocity.0.x = stats.jump.forward * direction * facing;
+                velocity.0.y = stats.jump.up;
+            }
+            CharacterState::Idle | CharacterState::MovingForward | CharacterState::MovingBackward
+                if proximity_guard =>
+            {
+                character.state = CharacterState::Guarding;
+                character.just_transitioned = true;
+            }
+            CharacterState::Guarding => {
+                if !proximity_guard {
+                    character.state = CharacterState::Idle;
+                    character.just_transitioned = true;
+                }
+            }
             CharacterState::Idle => {
                 if character.input_dir.flipped(character.facing) == AbsoluteDirection::Right {
                     character.state = CharacterState::MovingForward;
@@ -205,66 +434,139 @@ pub(crate) fn state_manager(
             _ => {}
         }
 
-        for mov in &movelist.moves {
-            if mov.valid_in_states.matches(character.state)
+        // Moves are listed strongest first, so EX and super versions win over the plain ones
+        // when their inputs and meter are available.
+        let context = StateContext {
+            current_move: character.current_move.as_deref(),
+            move_frame: frameticker.current_frame - character.move_started,
+            move_hit: character.current_move_hit,
+            ..character.state_context(position, meter.bars(&rules))
+        };
+        let chosen = movelist.moves.iter().enumerate().find(|(_, mov)| {
+            mov.valid_in_states.matches(&context)
+                && context.meter_bars >= mov.meter_cost
                 && mov.input_matcher.matches(
                     input_history,
                     character.facing,
                     frameticker.current_frame,
                 )
-            {
-                character.state = mov.to_state;
-                character.just_transitioned = false;
-                character.current_move_on_hit = Some(mov.effects.clone());
-                animator.animation = mov.animation.clone();
-                sprite.index = 0;
-                animator.last_frame_change = frameticker.current_frame;
-                animator.just_changed_animation = true;
-                animator.idle_after_animation = true;
-
-                println!("{}: Player used {}", frameticker.current_frame, mov.name);
+        });
+        if let Some((move_id, mov)) = chosen {
+            meter.spend(mov.meter_cost, &rules);
+            meter.gain(mov.meter_gain, &rules);
+            if mov.freeze_frames > 0 {
+                frameticker.freeze = mov.freeze_frames;
             }
+            let from = character.state;
+            character.state = mov.to_state;
+            // The move's animation stands in for the state's, so leaving the move can pick a
+            // transition out of it.
+            character.animated_state = mov.to_state;
+            character.just_transitioned = false;
+            character.current_move = Some(mov.name.clone());
+            character.current_move_id = Some(move_id);
+            character.current_move_on_hit = Some(mov.effects.clone());
+            character.current_move_attacks = mov.attacks.clone();
+            character.armor = mov.armor;
+            character.move_started = frameticker.current_frame;
+            character.current_move_hit = false;
+            character.current_move_contacts.clear();
+            velocity.0.x = FixedPoint::ZERO;
+            animator.play(
+                &mut sprite,
+                mov.animation.clone(),
+                mov.start_frame,
+                mov.playback,
+                mov.speed,
+            );
+
+            events.moves.send(MoveStartedEvent {
+                frame: frameticker.current_frame,
+                entity,
+                move_id,
+                move_name: mov.name.clone(),
+                from,
+                to: mov.to_state,
+            });
         }
 
-        if character.just_transitioned {
-            // println!("JUST TRANSITIONED");
+        let context = character.state_context(position, meter.bars(&rules));
+        let previous = StateContext {
+            state: character.animated_state,
+            ..context
+        };
+        let turned = character.current_move.is_none()
+            && character.facing != character.animated_facing;
+        if character.just_transitioned
+            || (turned && graph.transition(&previous, &context, true).is_some())
+        {
             character.just_transitioned = false;
-            let (id, stop_after) = match character.state {
-                CharacterState::Idle => ("idle", false),
-                CharacterState::MovingForward => {
-                    if character.new_anim {
-                        ("walking_forward_2", false)
-                    } else {
-                        ("walking_forward", false)
+            character.current_move = None;
+            character.current_move_id = None;
+            character.current_move_on_hit = None;
+            character.current_move_attacks.clear();
+            character.current_move_hit = false;
+            character.current_move_contacts.clear();
+            character.armor = 0;
+
+            match graph.choose(&previous, &context, turned) {
+                Some(choice) => {
+                    let animation = |name: &str| {
+                        let handle = character.animations.get(name).cloned();
+                        if handle.is_none() {
+                            warn!(
+                                "{}: Animation graph refers to missing animation '{name}'",
+                                frameticker.current_frame
+                            );
+                        }
+                        handle
+                    };
+                    let state_animation = animation(choice.animation);
+                    match (choice.transition.and_then(animation), state_animation) {
+                        (Some(transition), state_animation) => {
+                            animator.play(
+                                &mut sprite,
+                                transition,
+                                0,
+                                Some(PlaybackMode::OnceThenHold),
+                                None,
+                            );
+                            animator.queued = state_animation;
+                        }
+                        (None, Some(state_animation)) => {
+                            animator.play(&mut sprite, state_animation, 0, None, None)
+                        }
+                        (None, None) => {}
                     }
                 }
-                CharacterState::MovingBackward => ("walking_backward", false),
-                CharacterState::Hitstun(_) => ("idle", false),
-                CharacterState::Blockstun(_) => ("idle", false),
-                _ => unreachable!(),
-            };
-
-            let anim = &character.animations[id];
-            animator.animation = anim.clone();
-            animator.last_frame_change = frameticker.current_frame;
-            sprite.index = 0;
-            animator.just_changed_animation = true;
-            animator.idle_after_animation = stop_after;
+                None => warn!(
+                    "{}: Animation graph has no animation for state {:?}",
+                    frameticker.current_frame, character.state
+                ),
+            }
+            character.animated_state = character.state;
+        }
+        if character.current_move.is_none() {
+            character.animated_facing = character.facing;
         }
 
         let modifier = if character.facing == FacingDirection::Right {
-            1.0
+            FixedPoint::from(1.0)
         } else {
-            -1.0
+            FixedPoint::from(-1.0)
         };
 
         match character.state {
-            CharacterState::MovingForward => velocity.0.x = FixedPoint::from(1.0 * modifier),
-            CharacterState::MovingBackward => velocity.0.x = FixedPoint::from(-1.0 * modifier),
-            CharacterState::Blockstun(_) | CharacterState::Hitstun(_) => {
+            CharacterState::MovingForward => velocity.0.x = stats.walk_forward_speed * modifier,
+            CharacterState::MovingBackward => velocity.0.x = -stats.walk_backward_speed * modifier,
+            CharacterState::Blockstun(_)
+            | CharacterState::Hitstun(_)
+            | CharacterState::GuardCrush(_) => {
                 velocity.0.x *= FixedPoint::from(0.9);
                 // println!("{:?}", velocity.0.x);
             }
+            CharacterState::Normal => velocity.0.x *= FixedPoint::from(0.9),
+            CharacterState::Jumping | CharacterState::Launched | CharacterState::WallSplat(_) => {}
             _ => velocity.0.x = FixedPoint::from(0.0),
         }
     }
@@ -0,0 +1,295 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use bevy::{
+    asset::{AssetLoader, AssetPath, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    anim_file::PlaybackMode,
+    animation::Animation,
+    animation_graph::AnimationGraph,
+    character::{Character, CharacterState, Health, Stats},
+    effects::Effect,
+    fixedpoint::FixedPoint,
+    movelist::{InputMatcher, Move, Movelist, StateMatcher},
+    tag::Tag,
+};
+
+pub fn init(app: &mut App) {
+    app.add_asset::<CharacterDefinition>();
+    app.add_asset_loader(CharacterDefinitionLoader);
+    app.add_system(reload_definitions);
+}
+
+struct CharacterDefinitionLoader;
+
+impl AssetLoader for CharacterDefinitionLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async {
+            let file: CharacterFileData = serde_json::from_slice(bytes)?;
+
+            let mut dependencies = vec![];
+            let animations = file
+                .animations
+                .into_iter()
+                .map(|(name, path)| {
+                    let path = AssetPath::new(PathBuf::from(path), None);
+                    let handle = load_context.get_handle(path.clone());
+                    dependencies.push(path);
+                    (name, handle)
+                })
+                .collect::<HashMap<String, Handle<Animation>>>();
+
+            // Characters start out playing it, before the animation graph picks anything.
+            if !animations.contains_key("idle") {
+                return Err(bevy::asset::Error::msg("character has no 'idle' animation"));
+            }
+            for mov in &file.moves {
+                if !animations.contains_key(&mov.animation) {
+                    return Err(bevy::asset::Error::msg(format!(
+                        "move '{}' uses unknown animation '{}'",
+                        mov.name, mov.animation
+                    )));
+                }
+            }
+            for name in file.animation_graph.animation_names() {
+                if !animations.contains_key(name) {
+                    return Err(bevy::asset::Error::msg(format!(
+                        "animation graph uses unknown animation '{name}'"
+                    )));
+                }
+            }
+
+            let definition = CharacterDefinition {
+                name: file.name,
+                health: file.health,
+                stats: file.stats,
+                animations,
+                animation_graph: file.animation_graph,
+                moves: file.moves,
+            };
+
+            load_context
+                .set_default_asset(LoadedAsset::new(definition).with_dependencies(dependencies));
+
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["character"]
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CharacterFileData {
+    name: String,
+    health: FixedPoint,
+    #[serde(flatten)]
+    stats: Stats,
+    /// Animation names mapped to `.anim` paths relative to the asset folder.
+    animations: HashMap<String, String>,
+    animation_graph: AnimationGraph,
+    moves: Vec<MoveData>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MoveData {
+    name: String,
+    input: InputMatcher,
+    valid_in_states: StateMatcher,
+    to_state: CharacterState,
+    /// Name of an entry in the character's animation table.
+    animation: String,
+    effects: Vec<Effect>,
+    #[serde(default)]
+    attacks: HashMap<String, Vec<Effect>>,
+    #[serde(default = "zero")]
+    meter_gain: FixedPoint,
+    #[serde(default)]
+    meter_cost: usize,
+    #[serde(default)]
+    freeze_frames: usize,
+    #[serde(default)]
+    armor: usize,
+    /// Overrides the playback mode of the animation file. Attack animations set
+    /// `once_then_return` themselves, so the move ends when they are over.
+    #[serde(default)]
+    playback: Option<PlaybackMode>,
+    #[serde(default)]
+    speed: Option<FixedPoint>,
+    #[serde(default)]
+    start_frame: usize,
+}
+
+fn zero() -> FixedPoint {
+    FixedPoint::ZERO
+}
+
+#[derive(TypeUuid)]
+#[uuid = "0b8f7d0c-61d2-4a3e-9d1b-7f6f3b2c9e51"]
+pub struct CharacterDefinition {
+    pub name: String,
+    pub health: FixedPoint,
+    pub stats: Stats,
+    pub animations: HashMap<String, Handle<Animation>>,
+    pub animation_graph: AnimationGraph,
+    moves: Vec<MoveData>,
+}
+
+impl CharacterDefinition {
+    pub fn movelist(&self) -> Movelist {
+        Movelist {
+            moves: self
+                .moves
+                .iter()
+                .map(|mov| Move {
+                    name: mov.name.clone(),
+                    input_matcher: mov.input.clone(),
+                    valid_in_states: mov.valid_in_states.clone(),
+                    to_state: mov.to_state,
+                    animation: self.animations[&mov.animation].clone(),
+                    effects: mov.effects.clone(),
+                    attacks: mov
+                        .attacks
+                        .iter()
+                        .map(|(name, effects)| (Tag::new(name), effects.clone()))
+                        .collect(),
+                    meter_gain: mov.meter_gain,
+                    meter_cost: mov.meter_cost,
+                    freeze_frames: mov.freeze_frames,
+                    armor: mov.armor,
+                    playback: mov.playback,
+                    speed: mov.speed,
+                    start_frame: mov.start_frame,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Applies an edited character file to every character spawned from it.
+fn reload_definitions(
+    mut events: EventReader<AssetEvent<CharacterDefinition>>,
+    definitions: Res<Assets<CharacterDefinition>>,
+    mut query: Query<(
+        &Handle<CharacterDefinition>,
+        &mut Character,
+        &mut Movelist,
+        &mut AnimationGraph,
+        &mut Stats,
+        &mut Health,
+    )>,
+) {
+    for event in events.iter() {
+        let AssetEvent::Modified { handle } = event else {
+            continue;
+        };
+        let Some(definition) = definitions.get(handle) else {
+            continue;
+        };
+        info!("Reloading character {}", definition.name);
+
+        for (_, mut character, mut movelist, mut graph, mut stats, mut health) in
+            query.iter_mut().filter(|(h, ..)| *h == handle)
+        {
+            character.animations = definition.animations.clone();
+            *movelist = definition.movelist();
+            *graph = definition.animation_graph.clone();
+            *stats = definition.stats.clone();
+            health.max = definition.health;
+            health.value = health.value.min(health.max);
+
+            // Moves keep playing their old animation until they end, everything else picks
+            // its animation from the new table right away.
+            if character.current_move.is_none() {
+                character.just_transitioned = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        character::{FacingDirection, InputAction, InputActionKind, InputHistory},
+        movelist::{Button, StateContext},
+        AbsoluteDirection,
+    };
+
+    fn c1() -> CharacterFileData {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/c1.character");
+        serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    fn context(state: CharacterState) -> StateContext<'static> {
+        StateContext {
+            state,
+            airborne: false,
+            current_move: None,
+            move_frame: 0,
+            move_hit: false,
+            meter_bars: 0,
+        }
+    }
+
+    fn transition(file: &CharacterFileData, from: CharacterState, to: CharacterState) -> &str {
+        let choice = file
+            .animation_graph
+            .choose(&context(from), &context(to), false)
+            .unwrap();
+        let transition = choice.transition.unwrap();
+        assert!(file.animations.contains_key(transition), "{transition}");
+        transition
+    }
+
+    #[test]
+    fn c1_transitions_between_idle_walk_and_attacks() {
+        let file = c1();
+        let (idle, walking) = (CharacterState::Idle, CharacterState::MovingForward);
+        assert_eq!(transition(&file, idle, walking), "walk_start");
+        assert_eq!(transition(&file, walking, idle), "walk_stop");
+        assert_eq!(transition(&file, CharacterState::Normal, idle), "punch_recover");
+
+        let choice = file.animation_graph.choose(&context(idle), &context(idle), false);
+        assert!(choice.unwrap().transition.is_none());
+    }
+
+    #[test]
+    fn c1_super_and_ex_launchers_take_different_inputs() {
+        let file = c1();
+        let input = |name: &str| &file.moves.iter().find(|m| m.name == name).unwrap().input;
+        let pressed = |direction| {
+            let press = |button| InputAction {
+                time: 10,
+                kind: InputActionKind::PressButton(button),
+            };
+            InputHistory {
+                last_dir: direction,
+                move_buffer: vec![press(Button::Punch), press(Button::Kick)],
+            }
+        };
+        let matches = |name, history: &InputHistory| {
+            input(name).matches(history, FacingDirection::Right, 10)
+        };
+
+        let down = pressed(AbsoluteDirection::Down);
+        assert!(matches("Super Launcher", &down));
+        assert!(!matches("EX Launcher", &down));
+
+        let neutral = pressed(AbsoluteDirection::Neutral);
+        assert!(!matches("Super Launcher", &neutral));
+        assert!(matches("EX Launcher", &neutral));
+    }
+}
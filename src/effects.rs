@@ -1,59 +1,334 @@
 use crate::{
-    character::{Character, CharacterState, FacingDirection, Health},
+    animation::Hitboxes,
+    character::{Character, CharacterState, FacingDirection, Guard, Health, Meter},
+    events::{BlockEvent, CombatEvents, HitEvent, KoEvent, WhiffEvent},
     fixedpoint::FixedPoint,
-    Velocity, Frameticker,
+    rules::Rules,
+    stage::{Stage, WallReaction},
+    vec2::Vec2,
+    Velocity, Frameticker, Position,
 };
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 #[derive(Component)]
 pub struct Effects {
     pub effects: Vec<Effect>,
+    pub attacker: Option<Entity>,
+    /// Name of the move the effects came from, for combat events.
+    pub move_name: Option<String>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum Effect {
     Damage(FixedPoint),
     Hitstun(usize),
     Blockstun(usize),
     Pushback(FixedPoint),
+    /// Sets both velocity components, with `x` pointing away from the attacker.
+    Launch(Vec2),
+    /// How many juggle points the move consumes when it hits an airborne opponent.
+    JuggleCost(usize),
+    /// Bounces the opponent off the next wall they hit, at most once per combo.
+    WallBounce(Vec2),
+    /// Bounces the opponent back up with the given speed when they next land, at most once
+    /// per combo.
+    GroundBounce(FixedPoint),
+    /// Sticks the opponent to the next wall they hit for the given number of frames, at most
+    /// once per combo.
+    WallSplat(usize),
+    /// Damage dealt when blocked, overriding `Rules::chip_ratio`.
+    ChipDamage(FixedPoint),
+    /// Guard gauge taken when blocked, overriding `Rules::guard_damage_ratio`.
+    GuardDamage(FixedPoint),
+    /// How the hit can be blocked, `Mid` when left out.
+    Guard(GuardType),
+    /// Which invulnerability avoids the hit, `Strike` when left out.
+    Attack(AttackKind),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum AttackKind {
+    #[default]
+    Strike,
+    Throw,
+    Projectile,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum GuardType {
+    #[default]
+    Mid,
+    Unblockable,
+}
+
+impl Effect {
+    fn juggle_cost(effects: &[Effect]) -> usize {
+        effects
+            .iter()
+            .map(|effect| match effect {
+                Effect::JuggleCost(cost) => *cost,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    fn damage(effects: &[Effect]) -> FixedPoint {
+        effects.iter().fold(FixedPoint::ZERO, |acc, effect| match effect {
+            Effect::Damage(dmg) => acc + *dmg,
+            _ => acc,
+        })
+    }
+
+    fn chip_damage(effects: &[Effect]) -> Option<FixedPoint> {
+        effects.iter().find_map(|effect| match effect {
+            Effect::ChipDamage(dmg) => Some(*dmg),
+            _ => None,
+        })
+    }
+
+    pub fn attack_kind(effects: &[Effect]) -> AttackKind {
+        effects
+            .iter()
+            .find_map(|effect| match effect {
+                Effect::Attack(kind) => Some(*kind),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    fn guard_type(effects: &[Effect]) -> GuardType {
+        effects
+            .iter()
+            .find_map(|effect| match effect {
+                Effect::Guard(guard_type) => Some(*guard_type),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    fn guard_damage(effects: &[Effect]) -> Option<FixedPoint> {
+        effects.iter().find_map(|effect| match effect {
+            Effect::GuardDamage(dmg) => Some(*dmg),
+            _ => None,
+        })
+    }
 }
 
 pub(crate) fn apply_effects(
     frameticker: Res<Frameticker>,
-    mut query: Query<(&mut Health, &mut Velocity, &mut Character, &mut Effects)>,
+    stage: Res<Stage>,
+    rules: Res<Rules>,
+    positions: Query<&Position>,
+    mut query: Query<(
+        Entity,
+        &mut Health,
+        &mut Guard,
+        &mut Meter,
+        &mut Velocity,
+        &Position,
+        &Hitboxes,
+        &mut Character,
+        &mut Effects,
+    )>,
+    mut events: CombatEvents,
 ) {
-    for (mut health, mut velocity, mut character, mut effects) in query.iter_mut() {
-        let blocking = matches!(
-            character.state,
-            CharacterState::MovingBackward | CharacterState::Blockstun(_)
-        );
-        for effect in std::mem::take(&mut effects.effects) {
-            println!("Applying effect");
+    let mut attacker_pushback = vec![];
+    let mut attacker_meter = vec![];
+
+    for (
+        entity,
+        mut health,
+        mut guard,
+        mut meter,
+        mut velocity,
+        position,
+        hitboxes,
+        mut character,
+        mut effects,
+    ) in query.iter_mut()
+    {
+        let attacker = effects.attacker.take();
+        let move_name = effects.move_name.take();
+        let blocking = matches!(character.state, CharacterState::Blockstun(_))
+            || character.state.can_guard()
+                && attacker
+                    .and_then(|attacker| positions.get(attacker).ok())
+                    .is_some_and(|attacker_pos| {
+                        character.is_holding_guard(position.0.x, attacker_pos.0.x, &rules)
+                    });
+        let mut effects = std::mem::take(&mut effects.effects);
+        if effects.is_empty() {
+            continue;
+        }
+        let blocking = blocking && Effect::guard_type(&effects) != GuardType::Unblockable;
+
+        // Armor takes the damage and ignores everything else, except for throws.
+        let armored = !blocking
+            && character.armor > 0
+            && Effect::attack_kind(&effects) != AttackKind::Throw;
+        if armored {
+            character.armor -= 1;
+            effects.retain(|effect| matches!(effect, Effect::Damage(_)));
+        }
+
+        let airborne = character.state.is_juggled();
+        let launching = effects.iter().any(|e| matches!(e, Effect::Launch(_)));
+        if airborne || (launching && !blocking) {
+            let cost = Effect::juggle_cost(&effects);
+            if airborne && character.combo.juggle_points + cost > rules.juggle_limit {
+                if let Some(attacker) = attacker {
+                    events.whiffs.send(WhiffEvent {
+                        frame: frameticker.current_frame,
+                        attacker,
+                        defender: Some(entity),
+                        move_name,
+                    });
+                }
+                continue;
+            }
+            character.combo.juggle_points += cost;
+        }
+
+        let health_before = health.value;
+        let mut crushed = false;
+        if blocking {
+            let chip = Effect::chip_damage(&effects)
+                .unwrap_or(Effect::damage(&effects) * rules.chip_ratio);
+            if rules.chip_can_ko {
+                health.value -= chip;
+            } else {
+                let floor = health.value.min(FixedPoint::from(1.0));
+                health.value = (health.value - chip).max(floor);
+            }
+
+            guard.value -= Effect::guard_damage(&effects)
+                .unwrap_or(Effect::damage(&effects) * rules.guard_damage_ratio);
+            if guard.value <= FixedPoint::ZERO {
+                guard.value = FixedPoint::ZERO;
+                crushed = true;
+            }
+        }
+
+        // Away from where the attacker actually is, so cross-ups push the other way. Facing
+        // only decides when they are right on top of each other.
+        let attacker_x = attacker
+            .and_then(|attacker| positions.get(attacker).ok())
+            .map(|attacker_pos| attacker_pos.0.x);
+        let direction = match attacker_x {
+            Some(x) if x > position.0.x => FixedPoint::from(-1.0),
+            Some(x) if x < position.0.x => FixedPoint::from(1.0),
+            _ if character.facing == FacingDirection::Right => FixedPoint::from(-1.0),
+            _ => FixedPoint::from(1.0),
+        };
+        let span = rules.collision_matrix.wall_span(&hitboxes.hitboxes);
+
+        for effect in effects {
             match effect {
-                Effect::Damage(dmg) if blocking => health.value -= dmg * FixedPoint::from(0.2),
                 Effect::Damage(dmg) if !blocking => health.value -= dmg,
+                Effect::Hitstun(_) if character.state.is_juggled() => {}
                 Effect::Hitstun(frames) if !blocking => {
-                    println!("{}: Player entered hitstun", frameticker.current_frame);
                     character.state = CharacterState::Hitstun(frames);
                     character.just_transitioned = true;
                 }
-                Effect::Blockstun(frames) if blocking => {
-                    println!("{}: Player entered blockstun", frameticker.current_frame);
+                Effect::Blockstun(frames) if blocking && !crushed => {
                     character.state = CharacterState::Blockstun(frames);
                     character.just_transitioned = true;
                 }
+                Effect::Pushback(vel) if stage.is_cornered(position.0.x, span, direction) => {
+                    if let Some(attacker) = attacker {
+                        attacker_pushback.push((attacker, -(vel * direction)));
+                    }
+                }
                 Effect::Pushback(vel) => {
-                    velocity.0.x = vel
-                        * if character.facing == FacingDirection::Right {
-                            FixedPoint::from(-1.0)
-                        } else {
-                            FixedPoint::from(1.0)
-                        };
+                    velocity.0.x = vel * direction;
 
                     // println!("{:?}", velocity.0.x);
                 }
+                Effect::Launch(vel) if !blocking => {
+                    velocity.0.x = vel.x * direction;
+                    velocity.0.y = vel.y;
+                    character.state = CharacterState::Launched;
+                    character.just_transitioned = true;
+                }
+                Effect::WallBounce(vel) if !blocking && !character.combo.wall_bounced => {
+                    character.combo.pending_wall_reaction = Some(WallReaction::Bounce(vel));
+                }
+                Effect::WallSplat(frames) if !blocking && !character.combo.wall_splatted => {
+                    character.combo.pending_wall_reaction = Some(WallReaction::Splat(frames));
+                }
+                Effect::GroundBounce(speed) if !blocking && !character.combo.ground_bounced => {
+                    character.combo.pending_ground_bounce = Some(speed);
+                }
                 _ => {}
             }
         }
+
+        if crushed {
+            character.state = CharacterState::GuardCrush(rules.guard_crush_frames);
+            character.just_transitioned = true;
+        }
+
+        let damage_taken = health_before - health.value;
+        meter.gain(damage_taken * rules.meter_per_damage_taken, &rules);
+        if let Some(attacker) = attacker {
+            attacker_meter.push((attacker, damage_taken * rules.meter_per_damage_dealt));
+
+            if blocking {
+                events.blocks.send(BlockEvent {
+                    frame: frameticker.current_frame,
+                    attacker,
+                    defender: entity,
+                    move_name: move_name.clone(),
+                    damage: damage_taken,
+                });
+            } else {
+                events.hits.send(HitEvent {
+                    frame: frameticker.current_frame,
+                    attacker,
+                    defender: entity,
+                    move_name: move_name.clone(),
+                    damage: damage_taken,
+                    armored,
+                });
+            }
+        }
+
+        if health_before > FixedPoint::ZERO && health.value <= FixedPoint::ZERO {
+            events.kos.send(KoEvent {
+                frame: frameticker.current_frame,
+                attacker,
+                defender: entity,
+                move_name,
+                damage: damage_taken,
+            });
+        }
+    }
+
+    // The attacker may be gone already, like a projectile that expired on the frame it hit.
+    for (attacker, vel) in attacker_pushback {
+        if let Ok(mut velocity) = query.get_component_mut::<Velocity>(attacker) {
+            velocity.0.x = vel;
+        }
+    }
+    for (attacker, amount) in attacker_meter {
+        if let Ok(mut meter) = query.get_component_mut::<Meter>(attacker) {
+            meter.gain(amount, &rules);
+        }
+    }
+}
+
+pub(crate) fn regenerate_guard(
+    rules: Res<Rules>,
+    mut query: Query<(&mut Guard, &Character)>,
+) {
+    for (mut guard, character) in query.iter_mut() {
+        match character.state {
+            // The crush ends next frame with a full gauge, so the next block doesn't crush
+            // again right away.
+            CharacterState::GuardCrush(0) => guard.value = rules.max_guard,
+            CharacterState::Blockstun(_) | CharacterState::GuardCrush(_) => {}
+            _ => guard.value = (guard.value + rules.guard_regen).min(rules.max_guard),
+        }
     }
 }
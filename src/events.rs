@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    character::{Character, CharacterState},
+    fixedpoint::FixedPoint,
+    stage::Wall,
+    Frameticker,
+};
+
+pub fn init(app: &mut App) {
+    app.add_event::<HitEvent>();
+    app.add_event::<BlockEvent>();
+    app.add_event::<WhiffEvent>();
+    app.add_event::<ClashEvent>();
+    app.add_event::<MoveStartedEvent>();
+    app.add_event::<StateChangedEvent>();
+    app.add_event::<KoEvent>();
+    app.add_event::<SurfaceReactionEvent>();
+    app.add_system(log_events);
+}
+
+/// Writers for every event a system taking part in combat might send.
+#[derive(SystemParam)]
+pub struct CombatEvents<'w> {
+    pub hits: EventWriter<'w, HitEvent>,
+    pub blocks: EventWriter<'w, BlockEvent>,
+    pub whiffs: EventWriter<'w, WhiffEvent>,
+    pub clashes: EventWriter<'w, ClashEvent>,
+    pub moves: EventWriter<'w, MoveStartedEvent>,
+    pub kos: EventWriter<'w, KoEvent>,
+}
+
+/// An attack landed on an opponent who wasn't blocking.
+#[derive(Clone, Debug)]
+pub struct HitEvent {
+    pub frame: usize,
+    pub attacker: Entity,
+    pub defender: Entity,
+    pub move_name: Option<String>,
+    /// Health the defender lost.
+    pub damage: FixedPoint,
+    /// Whether the defender's armor absorbed the hit, so only the damage was applied.
+    pub armored: bool,
+}
+
+/// An attack was blocked.
+#[derive(Clone, Debug)]
+pub struct BlockEvent {
+    pub frame: usize,
+    pub attacker: Entity,
+    pub defender: Entity,
+    pub move_name: Option<String>,
+    /// Chip damage taken by the defender.
+    pub damage: FixedPoint,
+}
+
+/// An attack missed. `defender` is set when it touched someone it couldn't hit, because they
+/// were invulnerable or out of juggle points, and unset when the move ended without touching
+/// anyone.
+#[derive(Clone, Debug)]
+pub struct WhiffEvent {
+    pub frame: usize,
+    pub attacker: Entity,
+    pub defender: Option<Entity>,
+    pub move_name: Option<String>,
+}
+
+/// The attacks of two characters met and cancelled each other.
+#[derive(Clone, Debug)]
+pub struct ClashEvent {
+    pub frame: usize,
+    pub entities: [Entity; 2],
+    pub move_names: [Option<String>; 2],
+}
+
+/// A character started a move, going from the state it was in to the move's state.
+#[derive(Clone, Debug)]
+pub struct MoveStartedEvent {
+    pub frame: usize,
+    pub entity: Entity,
+    /// Index of the move in the character's `Movelist`.
+    pub move_id: usize,
+    pub move_name: String,
+    pub from: CharacterState,
+    pub to: CharacterState,
+}
+
+/// Sent by `state_changes` for every character whose state differs from the previous tick.
+/// Counting down the frames of a state doesn't count as a change.
+#[derive(Clone, Debug)]
+pub struct StateChangedEvent {
+    pub frame: usize,
+    pub entity: Entity,
+    /// The move the character is performing in the new state, if any.
+    pub move_id: Option<usize>,
+    pub from: CharacterState,
+    pub to: CharacterState,
+}
+
+/// A character's health ran out.
+#[derive(Clone, Debug)]
+pub struct KoEvent {
+    pub frame: usize,
+    pub attacker: Option<Entity>,
+    pub defender: Entity,
+    pub move_name: Option<String>,
+    /// Damage of the finishing hit.
+    pub damage: FixedPoint,
+}
+
+/// A juggled character landed on the ground or reached a wall with a reaction pending from
+/// the hit that launched them.
+#[derive(Clone, Debug)]
+pub struct SurfaceReactionEvent {
+    pub frame: usize,
+    pub entity: Entity,
+    pub reaction: SurfaceReaction,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SurfaceReaction {
+    GroundBounce,
+    WallBounce(Wall),
+    WallSplat(Wall),
+}
+
+/// Compares every character's state with the one it had at the end of the previous tick.
+pub(crate) fn state_changes(
+    frameticker: Res<Frameticker>,
+    query: Query<(Entity, &Character)>,
+    mut previous: Local<HashMap<Entity, CharacterState>>,
+    mut events: EventWriter<StateChangedEvent>,
+) {
+    for (entity, character) in query.iter() {
+        let from = previous.insert(entity, character.state);
+        let changed = |from: &CharacterState| {
+            std::mem::discriminant(from) != std::mem::discriminant(&character.state)
+        };
+        if let Some(from) = from.filter(changed) {
+            events.send(StateChangedEvent {
+                frame: frameticker.current_frame,
+                entity,
+                move_id: character.current_move_id,
+                from,
+                to: character.state,
+            });
+        }
+    }
+}
+
+/// Logs every combat event at debug level, shown when running with `RUST_LOG=fg=debug`.
+fn log_events(
+    mut hits: EventReader<HitEvent>,
+    mut blocks: EventReader<BlockEvent>,
+    mut whiffs: EventReader<WhiffEvent>,
+    mut clashes: EventReader<ClashEvent>,
+    mut moves: EventReader<MoveStartedEvent>,
+    mut states: EventReader<StateChangedEvent>,
+    mut kos: EventReader<KoEvent>,
+    mut reactions: EventReader<SurfaceReactionEvent>,
+) {
+    for e in moves.iter() {
+        debug!(
+            "{}: {:?} used {} (move {}), going from {:?} to {:?}",
+            e.frame, e.entity, e.move_name, e.move_id, e.from, e.to
+        );
+    }
+    for e in hits.iter() {
+        let armored = if e.armored { " through armor" } else { "" };
+        debug!(
+            "{}: {:?} hit {:?} with {:?} for {}{armored}",
+            e.frame,
+            e.attacker,
+            e.defender,
+            e.move_name,
+            f32::from(e.damage)
+        );
+    }
+    for e in blocks.iter() {
+        debug!(
+            "{}: {:?} blocked {:?} from {:?}, taking {}",
+            e.frame,
+            e.defender,
+            e.move_name,
+            e.attacker,
+            f32::from(e.damage)
+        );
+    }
+    for e in whiffs.iter() {
+        match e.defender {
+            Some(defender) => debug!(
+                "{}: {:?} of {:?} passed through {:?}",
+                e.frame, e.move_name, e.attacker, defender
+            ),
+            None => debug!("{}: {:?} whiffed {:?}", e.frame, e.attacker, e.move_name),
+        }
+    }
+    for e in clashes.iter() {
+        debug!(
+            "{}: {:?} and {:?} clashed with {:?} and {:?}",
+            e.frame, e.entities[0], e.entities[1], e.move_names[0], e.move_names[1]
+        );
+    }
+    for e in states.iter() {
+        debug!(
+            "{}: {:?} went from {:?} to {:?} during move {:?}",
+            e.frame, e.entity, e.from, e.to, e.move_id
+        );
+    }
+    for e in kos.iter() {
+        debug!(
+            "{}: {:?} was knocked out by {:?} from {:?} for {}",
+            e.frame,
+            e.defender,
+            e.move_name,
+            e.attacker,
+            f32::from(e.damage)
+        );
+    }
+    for e in reactions.iter() {
+        let reaction = match e.reaction {
+            SurfaceReaction::GroundBounce => "bounced off the ground".to_string(),
+            SurfaceReaction::WallBounce(wall) => format!("bounced off the {wall:?} wall"),
+            SurfaceReaction::WallSplat(wall) => format!("splatted against the {wall:?} wall"),
+        };
+        debug!("{}: {:?} {reaction}", e.frame, e.entity);
+    }
+}
@@ -1,4 +1,6 @@
-use std::{ops::{Add, AddAssign, Mul, MulAssign, Sub, SubAssign}, fmt::Debug};
+use std::{ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign}, fmt::Debug};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct FixedPoint(i64);
@@ -18,6 +20,15 @@ impl FixedPoint {
     fn new_raw(raw: i64) -> Self {
         Self(raw)
     }
+
+    pub fn abs(self) -> Self {
+        Self(self.0.abs())
+    }
+
+    /// Rounds towards negative infinity and returns the whole part.
+    pub fn floor(self) -> i64 {
+        self.0 >> Self::DECIMALS
+    }
 }
 
 impl Add for FixedPoint {
@@ -62,6 +73,22 @@ impl MulAssign for FixedPoint {
     }
 }
 
+impl Neg for FixedPoint {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self(-self.0)
+    }
+}
+
+impl Div for FixedPoint {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        Self((self.0 << Self::DECIMALS) / rhs.0)
+    }
+}
+
 impl From<FixedPoint> for f32 {
     fn from(value: FixedPoint) -> Self {
         value.0 as f32 / 2usize.pow(FixedPoint::DECIMALS as _) as f32
@@ -80,3 +107,23 @@ impl From<f32> for FixedPoint {
         }
     }
 }
+
+impl From<usize> for FixedPoint {
+    fn from(value: usize) -> Self {
+        Self((value as i64) << FixedPoint::DECIMALS)
+    }
+}
+
+// Data files write plain numbers; they are converted once on load, so the simulation itself
+// never touches floats.
+impl Serialize for FixedPoint {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_f32((*self).into())
+    }
+}
+
+impl<'de> Deserialize<'de> for FixedPoint {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        f32::deserialize(d).map(FixedPoint::from)
+    }
+}
@@ -0,0 +1,3 @@
+//! The parts of the game shared with the tools in `src/bin`.
+
+pub mod anim_file;
@@ -4,11 +4,18 @@
 #![feature(int_roundings)]
 
 mod animation;
+mod animation_graph;
 mod character;
+mod definition;
 mod effects;
+mod events;
 mod fixedpoint;
 mod movelist;
 mod physics;
+mod projectile;
+mod rules;
+mod stage;
+mod tag;
 mod ui;
 mod vec2;
 
@@ -19,21 +26,25 @@ use std::{
 };
 
 use animation::{Animation, Animator, Hitboxes};
+use fg::anim_file;
 use bevy::{
-    asset::{AssetPath, LoadState},
+    asset::{AssetPath, AssetPlugin, LoadState},
+    log::info,
     prelude::{
         App, AssetEvent, AssetServer, Assets, Camera2dBundle, ClearColor, Color, Commands,
-        Component, CoreSchedule, CoreSet, EventReader, FixedTime, GamepadButtonType, Handle, Image,
-        ImagePlugin, IntoSystemConfig, IntoSystemConfigs, KeyCode, Msaa, PluginGroup, Query, Res,
-        ResMut, Resource, Transform,
+        Component, CoreSchedule, CoreSet, Entity, EventReader, EventWriter, FixedTime,
+        GamepadButtonType, Handle, Image, ImagePlugin, IntoSystemConfig, IntoSystemConfigs,
+        KeyCode, Msaa, PluginGroup, Query, Res, ResMut, Resource, Transform,
     },
     render::{render_resource::FilterMode, texture::ImageSampler},
     sprite::{Anchor, Sprite, SpriteBundle, SpriteSheetBundle, TextureAtlas, TextureAtlasSprite},
     window::{Window, WindowPlugin},
     DefaultPlugins,
 };
-use character::{Health, InputAction, InputActionKind, InputHistory, Team, FacingDirection};
+use definition::CharacterDefinition;
+use character::{Guard, Health, Meter, InputAction, InputActionKind, InputHistory, Team, FacingDirection};
 use effects::{Effect, Effects};
+use events::{SurfaceReaction, SurfaceReactionEvent};
 use fixedpoint::FixedPoint;
 use leafwing_input_manager::{
     orientation::Rotation,
@@ -42,6 +53,9 @@ use leafwing_input_manager::{
 };
 use movelist::{InputMatcher, Move, Movelist, StateMatcher};
 use physics::Collisions;
+use rules::Rules;
+use serde::{Deserialize, Serialize};
+use stage::{Stage, WallReaction};
 use ui::setup_ui;
 use vec2::Vec2;
 
@@ -50,9 +64,16 @@ fn main() {
     app.insert_resource(Msaa::Off)
         .insert_resource(ClearColor(Color::rgb(0.7, 0.7, 0.9)))
         .insert_resource(Frameticker::default())
+        .insert_resource(Stage::default())
+        .insert_resource(Rules::default())
         .add_plugins(
             DefaultPlugins
                 .set(ImagePlugin::default_nearest())
+                .set(AssetPlugin {
+                    // Edited `.anim` and `.character` files are swapped into the running match.
+                    watch_for_changes: true,
+                    ..default()
+                })
                 .set(WindowPlugin {
                     primary_window: Some(Window {
                         title: "Fighting Game".into(),
@@ -64,25 +85,51 @@ fn main() {
         )
         .add_plugin(InputManagerPlugin::<Input>::default())
         .add_startup_systems((startup, ui::setup_ui))
-        .add_system(input);
+        .add_system(input)
+        .add_system(spawn_pending_players);
     animation::init(&mut app);
+    definition::init(&mut app);
+    events::init(&mut app);
     app.get_schedule_mut(CoreSchedule::FixedUpdate)
         .unwrap()
         .add_systems(
             (
-                character::facing_corrector,
-                character::input_manager,
-                character::state_manager,
-                velocity_system,
-                animation::animator,
-                physics::collisions,
-                physics::collision_resolver,
-                effects::apply_effects,
+                character::facing_corrector.run_if(not_frozen),
+                character::input_manager.run_if(not_frozen),
+                character::state_manager.run_if(not_frozen),
+                velocity_system.run_if(not_frozen),
+                animation::animator.run_if(not_frozen),
+                animation::apply_frame_events.run_if(not_frozen),
+                physics::proximity_guard.run_if(not_frozen),
+                physics::collisions.run_if(not_frozen),
+                physics::collision_resolver.run_if(not_frozen),
+                effects::apply_effects.run_if(not_frozen),
+                effects::regenerate_guard.run_if(not_frozen),
+                events::state_changes.run_if(not_frozen),
                 ui::ui_system,
                 render_system,
                 tick_frame,
             )
                 .chain(),
+        )
+        // The chain above is as long as a tuple of systems gets.
+        .add_system(
+            physics::push_apart
+                .run_if(not_frozen)
+                .after(animation::apply_frame_events)
+                .before(physics::proximity_guard),
+        )
+        .add_system(
+            projectile::spawn_projectiles
+                .run_if(not_frozen)
+                .after(animation::apply_frame_events)
+                .before(physics::collisions),
+        )
+        .add_system(
+            projectile::move_projectiles
+                .run_if(not_frozen)
+                .after(physics::collision_resolver)
+                .before(effects::apply_effects),
         );
     app.run();
 }
@@ -118,14 +165,22 @@ struct Player(usize);
 struct Frameticker {
     current_frame: usize,
     pause: bool,
+    /// Remaining frames of a super freeze, during which gameplay systems don't run.
+    freeze: usize,
 }
 
 fn tick_frame(mut frame_ticker: ResMut<Frameticker>) {
-    if !frame_ticker.pause {
+    if frame_ticker.freeze > 0 {
+        frame_ticker.freeze -= 1;
+    } else if !frame_ticker.pause {
         frame_ticker.current_frame += 1;
     }
 }
 
+fn not_frozen(frame_ticker: Res<Frameticker>) -> bool {
+    frame_ticker.freeze == 0
+}
+
 // fn animation(
 //     mut query: Query<(&Position, &mut TextureAtlasSprite, &mut Animation, &Player)>,
 //     mut q2: Query<(&Position, &Player)>,
@@ -193,26 +248,61 @@ fn startup(
     );
     let player_idle = texture_atlases.add(texture_atlas);
 
-    let anim = asset_server.load("c1_walking_v2.anim");
+    let definition: Handle<CharacterDefinition> = asset_server.load("c1.character");
 
-    spawn_player(
-        &mut commands,
-        1,
-        -50.0,
-        input_map_p1,
-        anim.clone(),
-        Team::Team1,
-        &asset_server,
-    );
-    spawn_player(
-        &mut commands,
-        2,
-        50.0,
-        input_map_p2,
-        anim,
-        Team::Team2,
-        &asset_server,
-    );
+    commands.insert_resource(PendingPlayers(vec![
+        PendingPlayer {
+            player: 1,
+            x_pos: -50.0,
+            input_map: input_map_p1,
+            team: Team::Team1,
+            definition: definition.clone(),
+        },
+        PendingPlayer {
+            player: 2,
+            x_pos: 50.0,
+            input_map: input_map_p2,
+            team: Team::Team2,
+            definition,
+        },
+    ]));
+}
+
+/// Players waiting for their character definition to load before they can be spawned.
+#[derive(Resource)]
+struct PendingPlayers(Vec<PendingPlayer>);
+
+struct PendingPlayer {
+    player: usize,
+    x_pos: f32,
+    input_map: InputMap<Input>,
+    team: Team,
+    definition: Handle<CharacterDefinition>,
+}
+
+fn spawn_pending_players(
+    mut commands: Commands,
+    mut pending: ResMut<PendingPlayers>,
+    definitions: Res<Assets<CharacterDefinition>>,
+    rules: Res<Rules>,
+) {
+    let (ready, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut pending.0)
+        .into_iter()
+        .partition(|p| definitions.get(&p.definition).is_some());
+    pending.0 = waiting;
+
+    for p in ready {
+        spawn_player(
+            &mut commands,
+            p.player,
+            p.x_pos,
+            p.input_map,
+            p.definition.clone(),
+            definitions.get(&p.definition).unwrap(),
+            p.team,
+            &rules,
+        );
+    }
 }
 
 fn spawn_player(
@@ -220,9 +310,10 @@ fn spawn_player(
     player: usize,
     x_pos: f32,
     input_map: InputMap<Input>,
-    player_idle: Handle<Animation>,
+    handle: Handle<CharacterDefinition>,
+    definition: &CharacterDefinition,
     team: Team,
-    asset_server: &AssetServer,
+    rules: &Rules,
 ) {
     // let bundle = SpriteBundle {
     //     texture: asset_server.load("player.png"),
@@ -233,26 +324,14 @@ fn spawn_player(
     //     ..default()
     // };
 
-    let path = asset_server.get_handle_path(&player_idle).unwrap();
-    let atlas_path = AssetPath::new(path.path().to_path_buf(), Some("spritesheet".into()));
-    let atlas = asset_server.get_handle(atlas_path);
-
+    // The texture atlas is swapped in by `animation::animator` once the animation has loaded.
     let mut bundle = SpriteSheetBundle {
         sprite: TextureAtlasSprite::new(0),
-        texture_atlas: atlas,
         ..default()
     };
     bundle.sprite.anchor = Anchor::BottomCenter;
 
-    let animations: HashMap<&str, Handle<Animation>> = [
-        ("idle", asset_server.load("c1_idle.anim")),
-        ("walking_forward", asset_server.load("c1_walking.anim")),
-        ("walking_forward_2", asset_server.load("c1_walking_v2.anim")),
-        ("walking_backward", asset_server.load("c1_walking_v2.anim")),
-        ("punching", asset_server.load("c1_punch.anim")),
-    ]
-    .into_iter()
-    .collect();
+    info!("Spawning player {} as {}", player, definition.name);
 
     commands
         .spawn(InputManagerBundle::<Input> {
@@ -274,32 +353,31 @@ fn spawn_player(
         })
         .insert(character::Character {
             just_transitioned: true,
-            animations: animations.clone(),
-            new_anim: true,
+            animations: definition.animations.clone(),
             ..default()
         })
-        .insert(Animator {
-            animation: player_idle,
-            last_frame_change: 0,
-            just_changed_animation: true,
-            idle_after_animation: false,
-        })
-        .insert(Movelist {
-            moves: vec![Move {
-                name: "Jab".into(),
-                input_matcher: InputMatcher::Button(movelist::Button::Punch),
-                valid_in_states: StateMatcher::all(),
-                to_state: character::CharacterState::Normal,
-                animation: animations["punching"].clone(),
-                effects: vec![Effect::Damage(10.0.into()), Effect::Hitstun(21), Effect::Blockstun(15), Effect::Pushback(0.8.into())],
-            }],
-        })
+        .insert(definition.stats.clone())
+        .insert(Animator::new(definition.animations["idle"].clone()))
+        .insert(definition.movelist())
+        .insert(definition.animation_graph.clone())
+        .insert(handle)
         .insert(Hitboxes { hitboxes: vec![] })
         .insert(Collisions { collisions: vec![] })
-        .insert(Effects { effects: vec![] })
+        .insert(Effects {
+            effects: vec![],
+            attacker: None,
+            move_name: None,
+        })
         .insert(team)
         .insert(Health {
-            value: 100.0.into(),
+            value: definition.health,
+            max: definition.health,
+        })
+        .insert(Guard {
+            value: rules.max_guard,
+        })
+        .insert(Meter {
+            value: FixedPoint::ZERO,
         })
         // .insert(Animation::new(0, 1, 30, 0))
         .insert(Player(player));
@@ -338,6 +416,7 @@ fn input(
                     time: frame_ticker.current_frame,
                     kind: InputActionKind::PressDirection(dir),
                 });
+                input_history.last_dir = dir;
             }
 
             if action_state.just_pressed(Input::Punch) {
@@ -379,22 +458,90 @@ fn input(
     );
 }
 
-fn velocity_system(mut query: Query<(&mut Position, &mut Velocity, &mut Character)>) {
-    query.for_each_mut(|(mut pos, mut vel, mut character)| {
+fn velocity_system(
+    frame_ticker: Res<Frameticker>,
+    stage: Res<Stage>,
+    rules: Res<Rules>,
+    mut query: Query<(
+        Entity,
+        &mut Position,
+        &mut Velocity,
+        &mut Character,
+        &mut character::Character,
+        &character::Stats,
+        &Hitboxes,
+    )>,
+    mut events: EventWriter<SurfaceReactionEvent>,
+) {
+    let mut react = |entity, reaction| {
+        events.send(SurfaceReactionEvent {
+            frame: frame_ticker.current_frame,
+            entity,
+            reaction,
+        })
+    };
+    query.for_each_mut(|(entity, mut pos, mut vel, mut character, mut fighter, stats, hitboxes)| {
+        if let character::CharacterState::WallSplat(_) = fighter.state {
+            vel.0 = Vec2 {
+                x: FixedPoint::ZERO,
+                y: FixedPoint::ZERO,
+            };
+            return;
+        }
+
         pos.0 = pos.0 + vel.0;
-        vel.0.y -= FixedPoint::from(0.2);
+        vel.0.y -= stats.jump.gravity;
 
         if pos.0.y < FixedPoint::ZERO {
             pos.0.y = FixedPoint::ZERO;
-            vel.0.y = FixedPoint::ZERO;
-            character.state = CharacterState::Grounded;
-        }
 
-        if pos.0.x < FixedPoint::from(-100.0) {
-            pos.0.x = FixedPoint::from(-100.0);
+            if fighter.state == character::CharacterState::Launched
+                && let Some(speed) = fighter.combo.pending_ground_bounce.take()
+            {
+                react(entity, SurfaceReaction::GroundBounce);
+                vel.0.y = speed;
+                fighter.combo.ground_bounced = true;
+            } else {
+                vel.0.y = FixedPoint::ZERO;
+                character.state = CharacterState::Grounded;
+
+                if matches!(
+                    fighter.state,
+                    character::CharacterState::Launched | character::CharacterState::Jumping
+                ) {
+                    fighter.state = character::CharacterState::Idle;
+                    fighter.combo = default();
+                    fighter.just_transitioned = true;
+                }
+            }
         }
-        if pos.0.x > FixedPoint::from(100.0) {
-            pos.0.x = FixedPoint::from(100.0);
+
+        let span = rules.collision_matrix.wall_span(&hitboxes.hitboxes);
+        if let Some(wall) = stage.wall_at(pos.0.x, span) {
+            pos.0.x = stage.clamp(pos.0.x, span);
+
+            if fighter.state.is_juggled()
+                && let Some(reaction) = fighter.combo.pending_wall_reaction.take()
+            {
+                match reaction {
+                    WallReaction::Bounce(bounce) => {
+                        react(entity, SurfaceReaction::WallBounce(wall));
+                        vel.0.x = bounce.x * wall.away();
+                        vel.0.y = bounce.y;
+                        fighter.combo.wall_bounced = true;
+                    }
+                    WallReaction::Splat(frames) => {
+                        react(entity, SurfaceReaction::WallSplat(wall));
+                        vel.0 = Vec2 {
+                            x: FixedPoint::ZERO,
+                            y: FixedPoint::ZERO,
+                        };
+                        fighter.state = character::CharacterState::WallSplat(frames);
+                        fighter.just_transitioned = true;
+                        fighter.combo.wall_splatted = true;
+                    }
+                }
+            }
         }
     });
 }
@@ -429,7 +576,7 @@ struct Position(Vec2);
 #[derive(Component)]
 struct Velocity(Vec2);
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
 pub enum AbsoluteDirection {
     Right,
     DownRight,
@@ -448,6 +595,14 @@ impl AbsoluteDirection {
         matches!(self, Self::Up | Self::UpRight | Self::UpLeft)
     }
 
+    fn is_left(&self) -> bool {
+        matches!(self, Self::Left | Self::UpLeft | Self::DownLeft)
+    }
+
+    fn is_right(&self) -> bool {
+        matches!(self, Self::Right | Self::UpRight | Self::DownRight)
+    }
+
     fn flipped(&self, facing: FacingDirection) -> Self {
         if facing == FacingDirection::Left {
             match self {
@@ -1,8 +1,15 @@
 use crate::{
+    anim_file::PlaybackMode,
     animation::Animation,
     character::{CharacterState, InputHistory, FacingDirection, InputActionKind}, effects::Effect,
+    fixedpoint::FixedPoint,
+    tag::Tag,
+    AbsoluteDirection,
 };
+use std::{collections::HashMap, fmt::Display};
+
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 #[derive(Component)]
 pub struct Movelist {
@@ -16,44 +23,197 @@ pub struct Move {
     pub to_state: CharacterState,
     pub animation: Handle<Animation>,
     pub effects: Vec<Effect>,
+    /// Effect sets referred to by the `attack` of individual hitboxes, for sweet spots and
+    /// multi-part attacks.
+    pub attacks: HashMap<Tag, Vec<Effect>>,
+    /// Meter gained when the move comes out, whether or not it hits.
+    pub meter_gain: FixedPoint,
+    /// Number of full meter bars the move requires and consumes.
+    pub meter_cost: usize,
+    /// Frames the whole match freezes for when the move starts, for super flashes.
+    pub freeze_frames: usize,
+    /// Number of hits the move absorbs, taking their damage but not their hitstun.
+    pub armor: usize,
+    /// Overrides the playback mode of the animation.
+    pub playback: Option<PlaybackMode>,
+    /// Overrides the playback speed of the animation.
+    pub speed: Option<FixedPoint>,
+    /// Frame of the animation the move starts on. Only moves have one, animations always
+    /// start from their first frame.
+    pub start_frame: usize,
+}
+
+/// Condition on the character's current state deciding whether a move can be used. Matchers
+/// are plain data, so they can be loaded from character files, compared and printed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateMatcher {
+    /// Free to act: idle, walking or guarding.
+    Neutral,
+    /// In any of the listed states. Payloads such as hitstun frames are ignored.
+    AnyOf(Vec<CharacterState>),
+    Grounded,
+    Airborne,
+    /// Performing the named move, optionally only on frames `start..end` counted from the
+    /// first frame of the move.
+    InMove {
+        name: String,
+        #[serde(default)]
+        frames: Option<(usize, usize)>,
+    },
+    /// The current move has hit the opponent.
+    OnHit,
+    /// At least this many full meter bars are available.
+    HasMeter(usize),
+    All(Vec<StateMatcher>),
+    Any(Vec<StateMatcher>),
+    Not(Box<StateMatcher>),
 }
 
-pub struct StateMatcher {
-    matcher: Box<dyn Fn(CharacterState) -> bool + Send + Sync>,
+/// Everything a `StateMatcher` can look at.
+pub struct StateContext<'a> {
+    pub state: CharacterState,
+    pub airborne: bool,
+    pub current_move: Option<&'a str>,
+    /// Frames since the current move started.
+    pub move_frame: usize,
+    pub move_hit: bool,
+    pub meter_bars: usize,
 }
 
 impl StateMatcher {
     pub fn idle() -> Self {
-        Self {
-            matcher: Box::new(|character_state| character_state == CharacterState::Idle),
-        }
+        Self::AnyOf(vec![CharacterState::Idle])
     }
 
     pub fn forward() -> Self {
-        Self {
-            matcher: Box::new(|character_state| character_state == CharacterState::MovingForward),
-        }
+        Self::AnyOf(vec![CharacterState::MovingForward])
     }
 
     pub fn backward() -> Self {
-        Self {
-            matcher: Box::new(|character_state| character_state == CharacterState::MovingBackward),
-        }
+        Self::AnyOf(vec![CharacterState::MovingBackward])
     }
 
     pub fn all() -> Self {
-        Self {
-            matcher: Box::new(|character_state| matches!(character_state, CharacterState::Idle | CharacterState::MovingBackward | CharacterState::MovingForward))
+        Self::Neutral
+    }
+
+    pub fn in_move(name: impl Into<String>) -> Self {
+        Self::InMove {
+            name: name.into(),
+            frames: None,
+        }
+    }
+
+    pub fn in_move_on_frames(name: impl Into<String>, start: usize, end: usize) -> Self {
+        Self::InMove {
+            name: name.into(),
+            frames: Some((start, end)),
+        }
+    }
+
+    pub fn and(self, other: Self) -> Self {
+        match self {
+            Self::All(mut matchers) => {
+                matchers.push(other);
+                Self::All(matchers)
+            }
+            matcher => Self::All(vec![matcher, other]),
+        }
+    }
+
+    pub fn or(self, other: Self) -> Self {
+        match self {
+            Self::Any(mut matchers) => {
+                matchers.push(other);
+                Self::Any(matchers)
+            }
+            matcher => Self::Any(vec![matcher, other]),
+        }
+    }
+
+    pub fn negate(self) -> Self {
+        Self::Not(Box::new(self))
+    }
+
+    pub fn matches(&self, context: &StateContext) -> bool {
+        match self {
+            Self::Neutral => matches!(
+                context.state,
+                CharacterState::Idle
+                    | CharacterState::MovingBackward
+                    | CharacterState::MovingForward
+                    | CharacterState::Guarding
+            ),
+            Self::AnyOf(states) => states
+                .iter()
+                .any(|state| std::mem::discriminant(state) == std::mem::discriminant(&context.state)),
+            Self::Grounded => !context.airborne,
+            Self::Airborne => context.airborne,
+            Self::InMove { name, frames } => {
+                context.current_move == Some(name.as_str())
+                    && frames.map_or(true, |(start, end)| {
+                        (start..end).contains(&context.move_frame)
+                    })
+            }
+            Self::OnHit => context.current_move.is_some() && context.move_hit,
+            Self::HasMeter(bars) => context.meter_bars >= *bars,
+            Self::All(matchers) => matchers.iter().all(|m| m.matches(context)),
+            Self::Any(matchers) => matchers.iter().any(|m| m.matches(context)),
+            Self::Not(matcher) => !matcher.matches(context),
         }
     }
+}
 
-    pub fn matches(&self, state: CharacterState) -> bool {
-        (self.matcher)(state)
+impl Display for StateMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn join(
+            f: &mut std::fmt::Formatter<'_>,
+            matchers: &[StateMatcher],
+            separator: &str,
+        ) -> std::fmt::Result {
+            for (i, matcher) in matchers.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(separator)?;
+                }
+                write!(f, "({matcher})")?;
+            }
+            Ok(())
+        }
+
+        match self {
+            Self::Neutral => f.write_str("neutral"),
+            Self::AnyOf(states) => write!(f, "any of {states:?}"),
+            Self::Grounded => f.write_str("grounded"),
+            Self::Airborne => f.write_str("airborne"),
+            Self::InMove { name, frames: None } => write!(f, "in move \"{name}\""),
+            Self::InMove {
+                name,
+                frames: Some((start, end)),
+            } => write!(f, "in move \"{name}\" on frames {start}..{end}"),
+            Self::OnHit => f.write_str("on hit"),
+            Self::HasMeter(bars) => write!(f, "has {bars} bar(s) of meter"),
+            Self::All(matchers) => join(f, matchers, " and "),
+            Self::Any(matchers) => join(f, matchers, " or "),
+            Self::Not(matcher) => write!(f, "not ({matcher})"),
+        }
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub enum InputMatcher {
     Button(Button),
+    /// Every button pressed within the buffer, e.g. two buttons together for an EX version.
+    Buttons(Vec<Button>),
+    /// `button` pressed with at least `strength`, which grows with the number of buttons
+    /// pressed together. Weaker versions also come out for stronger presses, so listing the
+    /// stronger version first picks it whenever its meter is available.
+    Strength { button: Button, strength: Strength },
+    /// `input` while holding `direction`, given as if facing right.
+    Holding {
+        direction: AbsoluteDirection,
+        input: Box<InputMatcher>,
+    },
 }
 
 impl InputMatcher {
@@ -71,11 +231,58 @@ impl InputMatcher {
                 }
                 false
             }
+            InputMatcher::Buttons(buttons) => buttons.iter().all(|button| {
+                InputMatcher::Button(*button).matches(input_history, facing_direction, current_frame)
+            }),
+            InputMatcher::Strength { button, strength } => {
+                Strength::of(input_history, current_frame) >= *strength
+                    && InputMatcher::Button(*button).matches(
+                        input_history,
+                        facing_direction,
+                        current_frame,
+                    )
+            }
+            InputMatcher::Holding { direction, input } => {
+                input_history.last_dir.flipped(facing_direction) == *direction
+                    && input.matches(input_history, facing_direction, current_frame)
+            }
+        }
+    }
+}
+
+/// How strongly a special was pressed, picking between its plain and EX versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strength {
+    /// A single button.
+    Normal,
+    /// Two or more buttons pressed together.
+    Ex,
+}
+
+impl Strength {
+    /// Strength of the buttons pressed within the input buffer.
+    fn of(input_history: &InputHistory, current_frame: usize) -> Strength {
+        let mut pressed = vec![];
+        for action in input_history.move_buffer.iter().rev() {
+            if current_frame - action.time > InputHistory::BUFFER {
+                break;
+            }
+            if let InputActionKind::PressButton(button) = action.kind
+                && !pressed.contains(&button)
+            {
+                pressed.push(button);
+            }
+        }
+        if pressed.len() >= 2 {
+            Strength::Ex
+        } else {
+            Strength::Normal
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Button {
     Punch,
     Kick,
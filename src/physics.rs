@@ -1,6 +1,13 @@
+use std::collections::HashMap;
+
 use crate::{
-    animation::{Hitbox2, Hitboxes, HitboxType},
-    Position, Frameticker, character::{Team, Character}, effects::Effects,
+    anim_file::HitboxKind,
+    animation::{Hitbox2, Hitboxes},
+    Position, Frameticker, character::{Team, Character},
+    effects::{AttackKind, Effect, Effects},
+    events::{ClashEvent, CombatEvents, WhiffEvent},
+    fixedpoint::FixedPoint, projectile::Projectile, rules::{PriorityRules, Rules},
+    stage::{Span, Stage}, tag::Tag,
 };
 use bevy::prelude::*;
 
@@ -13,68 +20,811 @@ pub struct Collision {
     pub other_entity: Entity,
     pub other_team: Team,
     pub did_the_hitting: bool,
+    /// Whether an attacking box of each entity met an attacking box of the other.
+    pub clash: bool,
+    /// The box of this entity that touched the other one.
+    pub hitbox_id: usize,
+    pub attack: Option<Tag>,
+    pub priority: i32,
+}
+
+/// Which kinds of boxes interact, as pairs of the attacking kind and the kind it hits, and
+/// pairs of attacking kinds that cancel each other out. Boxes of kinds without an entry pass
+/// through each other.
+pub struct CollisionMatrix {
+    pub hits: Vec<(HitboxKind, HitboxKind)>,
+    pub clashes: Vec<(HitboxKind, HitboxKind)>,
+    /// Pairs of kinds that can't overlap, pushing their characters apart.
+    pub pushes: Vec<(HitboxKind, HitboxKind)>,
+    /// Pairs of the kind marking an incoming attack and the kind it makes guard in place.
+    pub proximity: Vec<(HitboxKind, HitboxKind)>,
+    /// Kinds kept inside the stage walls.
+    pub walls: Vec<HitboxKind>,
+}
+
+impl CollisionMatrix {
+    pub fn hits(&self, attacker: HitboxKind, target: HitboxKind) -> bool {
+        self.hits.contains(&(attacker, target))
+    }
+
+    pub fn clashes(&self, a: HitboxKind, b: HitboxKind) -> bool {
+        self.clashes.contains(&(a, b)) || self.clashes.contains(&(b, a))
+    }
+
+    pub fn pushes(&self, a: HitboxKind, b: HitboxKind) -> bool {
+        self.pushes.contains(&(a, b)) || self.pushes.contains(&(b, a))
+    }
+
+    pub fn proximity(&self, attacker: HitboxKind, target: HitboxKind) -> bool {
+        self.proximity.contains(&(attacker, target))
+    }
+
+    /// How far the boxes kept inside the walls reach to each side of the character.
+    pub fn wall_span(&self, hitboxes: &[Hitbox2]) -> Span {
+        hitboxes
+            .iter()
+            .filter(|hb| self.walls.contains(&hb.kind))
+            .map(|hb| Span {
+                left: hb.offset.x,
+                right: hb.offset.x + hb.size.x,
+            })
+            .reduce(|a, b| Span {
+                left: a.left.min(b.left),
+                right: a.right.max(b.right),
+            })
+            .unwrap_or(Span::POINT)
+    }
+}
+
+impl Default for CollisionMatrix {
+    fn default() -> Self {
+        Self {
+            hits: vec![
+                (HitboxKind::Hitbox, HitboxKind::Hurtbox),
+                (HitboxKind::Throwbox, HitboxKind::Throwable),
+            ],
+            clashes: vec![(HitboxKind::Hitbox, HitboxKind::Hitbox)],
+            pushes: vec![(HitboxKind::Pushbox, HitboxKind::Pushbox)],
+            proximity: vec![(HitboxKind::Proximity, HitboxKind::Hurtbox)],
+            walls: vec![HitboxKind::Collision],
+        }
+    }
+}
+
+/// An axis aligned box in world space, with y pointing up.
+#[derive(Clone, Copy)]
+struct Bounds {
+    left: FixedPoint,
+    right: FixedPoint,
+    bottom: FixedPoint,
+    top: FixedPoint,
+}
+
+impl Bounds {
+    fn of(pos: &Position, hitbox: &Hitbox2) -> Bounds {
+        let left = pos.0.x + hitbox.offset.x;
+        let top = pos.0.y + hitbox.offset.y;
+        Bounds {
+            left,
+            right: left + hitbox.size.x,
+            bottom: top - hitbox.size.y,
+            top,
+        }
+    }
+
+    fn union(self, other: Bounds) -> Bounds {
+        Bounds {
+            left: self.left.min(other.left),
+            right: self.right.max(other.right),
+            bottom: self.bottom.min(other.bottom),
+            top: self.top.max(other.top),
+        }
+    }
+
+    /// Boxes that only touch at an edge don't overlap.
+    fn overlaps(&self, other: &Bounds) -> bool {
+        self.left < other.right
+            && self.right > other.left
+            && self.bottom < other.top
+            && self.top > other.bottom
+    }
+}
+
+/// Pairs of entities whose boxes might touch, found by sorting their bounds along x and
+/// sweeping over them. Pairs are ordered by entity so the narrow phase runs in the same order
+/// every time.
+fn broad_phase(entities: impl Iterator<Item = (Entity, Bounds)>) -> Vec<(Entity, Entity)> {
+    let mut bounds: Vec<_> = entities.collect();
+    bounds.sort_by_key(|(entity, b)| (b.left, *entity));
+
+    let mut pairs = vec![];
+    for (i, (a, abounds)) in bounds.iter().enumerate() {
+        for (b, bbounds) in &bounds[i + 1..] {
+            if bbounds.left >= abounds.right {
+                break;
+            }
+            if abounds.overlaps(bbounds) {
+                pairs.push((*a.min(b), *a.max(b)));
+            }
+        }
+    }
+    pairs.sort();
+    pairs
 }
 
-pub(crate) fn collisions(frame_ticker: Res<Frameticker>, mut query: Query<(Entity, &Team, &Position, &Hitboxes, &mut Collisions)>) {
+pub(crate) fn collisions(rules: Res<Rules>, mut query: Query<(Entity, &Team, &Position, &Hitboxes, &mut Collisions)>) {
+    let pairs = broad_phase(query.iter().filter_map(|(entity, _, pos, hitboxes, _)| {
+        hitboxes
+            .hitboxes
+            .iter()
+            .map(|hitbox| Bounds::of(pos, hitbox))
+            .reduce(Bounds::union)
+            .map(|bounds| (entity, bounds))
+    }));
 
-    let mut iterator = query.iter_combinations_mut();
-    while let Some([(aentity, ateam, apos, ahitboxes, mut acollisions), (bentity, bteam, bpos, bhitboxes, mut bcollisions)]) = iterator.fetch_next()  {
+    for (aentity, bentity) in pairs {
+        let [(_, ateam, apos, ahitboxes, mut acollisions), (_, bteam, bpos, bhitboxes, mut bcollisions)] =
+            query.get_many_mut([aentity, bentity]).unwrap();
         for ahitbox in &ahitboxes.hitboxes {
             for bhitbox in &bhitboxes.hitboxes {
-                if ahitbox.hitbox_type == bhitbox.hitbox_type {
+                let a_hits = rules.collision_matrix.hits(ahitbox.kind, bhitbox.kind);
+                let b_hits = rules.collision_matrix.hits(bhitbox.kind, ahitbox.kind);
+                let clash = rules.collision_matrix.clashes(ahitbox.kind, bhitbox.kind);
+                if !a_hits && !b_hits && !clash {
                     continue;
                 }
 
-                let apos = apos.0 + ahitbox.offset;
-                let asize = ahitbox.size;
-                let bpos = bpos.0 + bhitbox.offset;
-                let bsize = bhitbox.size;
-
-                if apos.x < bpos.x + bsize.x
-                    && apos.x + asize.x > bpos.x
-                    && apos.y > bpos.y - bsize.y
-                    && apos.y - asize.y < bpos.y
-                {
-                    println!("{}: COLLISION! {} and {}", frame_ticker.current_frame, ahitbox.tag, bhitbox.tag);
+                if Bounds::of(apos, ahitbox).overlaps(&Bounds::of(bpos, bhitbox)) {
                     acollisions.collisions.push(Collision {
                         other_entity: bentity,
                         other_team: *bteam,
-                        did_the_hitting: ahitbox.hitbox_type == HitboxType::Hitbox,
+                        did_the_hitting: a_hits,
+                        clash,
+                        hitbox_id: ahitbox.id,
+                        attack: ahitbox.attack,
+                        priority: ahitbox.priority,
                     });
                     bcollisions.collisions.push(Collision {
                         other_entity: aentity,
                         other_team: *ateam,
-                        did_the_hitting: bhitbox.hitbox_type == HitboxType::Hitbox,
+                        did_the_hitting: b_hits,
+                        clash,
+                        hitbox_id: bhitbox.id,
+                        attack: bhitbox.attack,
+                        priority: bhitbox.priority,
                     });
                 }
             }
         }
+    }
+}
 
-        // println!("{}, {}", ahitboxes.hitboxes.len(), bhitboxes.hitboxes.len());
+/// A hit found this frame, applied only once every hit of the frame is known.
+struct Hit {
+    /// The entity whose box hit.
+    attacker: Entity,
+    /// Who is credited with the hit, `attacker` itself or whoever fired it.
+    owner: Entity,
+    target: Entity,
+    hitbox_id: usize,
+    move_name: Option<String>,
+    effects: Vec<Effect>,
+    kind: AttackKind,
+    priority: i32,
+}
+
+impl Hit {
+    /// Whether this hit stops `other`, the hit landed the other way around on the same frame.
+    fn beats(&self, other: &Hit, rules: &PriorityRules) -> bool {
+        if (self.kind == AttackKind::Throw) != (other.kind == AttackKind::Throw) {
+            (self.kind == AttackKind::Throw) == rules.throws_beat_strikes
+        } else {
+            rules.higher_priority_wins && self.priority > other.priority
+        }
     }
 }
 
-pub fn collision_resolver(mut player_query: Query<(&Team, &mut Collisions, &mut Character, &mut Effects)>) {
-    let mut effects_to_apply = vec![];
+/// Settles every hit of the frame at once, so that the outcome doesn't depend on the order
+/// characters are visited in. Clashing attacks cancel each other, and characters hitting each
+/// other trade unless `Rules::priority` picks a winner. A box that hit or clashed with someone
+/// is spent on them for the rest of the move, however many frames they keep overlapping.
+pub fn collision_resolver(
+    mut frameticker: ResMut<Frameticker>,
+    rules: Res<Rules>,
+    mut player_query: Combatants,
+    mut events: CombatEvents,
+) {
+    let mut hits = vec![];
+    let mut clash_contacts = vec![];
 
-    for (team, mut collisions, mut character, mut effects) in player_query.iter_mut() {
-        for collision in std::mem::take(&mut collisions.collisions) {
-            if collision.other_team == *team {
-                println!("Skipping same team collision");
+    for (entity, team, mut collisions, character, projectile, _) in player_query.iter_mut() {
+        let collisions = std::mem::take(&mut collisions.collisions);
+        let Some(attacker) = Attacker::new(entity, character.as_deref(), projectile.as_deref())
+        else {
+            continue;
+        };
+
+        // Only the highest priority box hitting each target applies.
+        let mut best: Vec<Collision> = vec![];
+        for collision in collisions {
+            let contact = (collision.hitbox_id, collision.other_entity);
+            if collision.other_team == *team || attacker.contacts.contains(&contact) {
+                continue;
+            }
+            if collision.clash {
+                clash_contacts.push((entity, contact));
+            }
+            if !collision.did_the_hitting {
                 continue;
             }
-            if collision.did_the_hitting {
-                if let Some(effects) = &character.current_move_on_hit {
-                    effects_to_apply.push((collision.other_entity, effects.clone()));
+            match best.iter_mut().find(|c| c.other_entity == collision.other_entity) {
+                Some(current) => {
+                    if (collision.priority, std::cmp::Reverse(collision.hitbox_id))
+                        > (current.priority, std::cmp::Reverse(current.hitbox_id))
+                    {
+                        *current = collision;
+                    }
+                }
+                None => best.push(collision),
+            }
+        }
+
+        for collision in best {
+            let effects = collision
+                .attack
+                .as_ref()
+                .and_then(|attack| attacker.attacks.and_then(|attacks| attacks.get(attack)))
+                .or(attacker.on_hit);
+            if let Some(effects) = effects {
+                hits.push(Hit {
+                    attacker: entity,
+                    owner: attacker.owner,
+                    target: collision.other_entity,
+                    hitbox_id: collision.hitbox_id,
+                    move_name: attacker.move_name.cloned(),
+                    kind: Effect::attack_kind(effects),
+                    effects: effects.clone(),
+                    priority: collision.priority,
+                });
+            }
+        }
+    }
+
+    hits.sort_by_key(|hit| (hit.attacker, hit.target));
+    let mut clashes: Vec<(Entity, Entity)> = clash_contacts
+        .iter()
+        .map(|&(entity, (_, other))| (entity.min(other), entity.max(other)))
+        .collect();
+    clashes.sort();
+    clashes.dedup();
+
+    hits.retain(|hit| {
+        let invulnerable = player_query
+            .get_component::<Character>(hit.target)
+            .is_ok_and(|target| target.is_invulnerable_to(hit.kind));
+        if invulnerable {
+            events.whiffs.send(WhiffEvent {
+                frame: frameticker.current_frame,
+                attacker: hit.owner,
+                defender: Some(hit.target),
+                move_name: hit.move_name.clone(),
+            });
+        }
+        !invulnerable
+    });
+
+    for &(a, b) in &clashes {
+        let move_name = |entity| {
+            let (entity, _, _, character, projectile, _) = player_query.get(entity).ok()?;
+            Attacker::new(entity, character, projectile)?.move_name.cloned()
+        };
+        events.clashes.send(ClashEvent {
+            frame: frameticker.current_frame,
+            entities: [a, b],
+            move_names: [move_name(a), move_name(b)],
+        });
+        // The hits between them are cancelled, and spent along with the clashing boxes.
+        let (cancelled, kept): (Vec<Hit>, Vec<Hit>) = std::mem::take(&mut hits)
+            .into_iter()
+            .partition(|hit| [(a, b), (b, a)].contains(&(hit.attacker, hit.target)));
+        hits = kept;
+        for (entity, opponent) in [(a, b), (b, a)] {
+            let contacts = clash_contacts
+                .iter()
+                .filter(|&&(clasher, (_, other))| (clasher, other) == (entity, opponent))
+                .map(|&(_, contact)| contact)
+                .chain(
+                    cancelled
+                        .iter()
+                        .filter(|hit| hit.attacker == entity)
+                        .map(|hit| (hit.hitbox_id, hit.target)),
+                );
+            record_contacts(&mut player_query, entity, contacts);
+        }
+        frameticker.freeze = frameticker.freeze.max(rules.priority.clash_freeze);
+    }
+
+    // Decided before removing anything, so that a hit losing to one character can't change
+    // how another pair is settled.
+    let beaten: Vec<bool> = hits
+        .iter()
+        .map(|hit| {
+            hits.iter()
+                .find(|other| other.attacker == hit.target && other.target == hit.attacker)
+                .is_some_and(|other| other.beats(hit, &rules.priority))
+        })
+        .collect();
+
+    for (hit, _) in hits.into_iter().zip(beaten).filter(|(_, beaten)| !beaten) {
+        // Only characters take hits, whatever the collision matrix lets touch.
+        let Ok((.., Some(mut target))) = player_query.get_mut(hit.target) else {
+            continue;
+        };
+        target.effects.extend(hit.effects);
+        target.attacker = Some(hit.owner);
+        target.move_name = hit.move_name;
+        record_contacts(&mut player_query, hit.attacker, [(hit.hitbox_id, hit.target)]);
+    }
+}
+
+/// Everything that can hit or be hit: characters, and the projectiles they fire.
+type Combatants<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Team,
+        &'static mut Collisions,
+        Option<&'static mut Character>,
+        Option<&'static mut Projectile>,
+        Option<&'static mut Effects>,
+    ),
+>;
+
+/// The attack behind a box, from a character's current move or from a projectile.
+struct Attacker<'a> {
+    /// Who is credited with the hit, the character itself or whoever fired the projectile.
+    owner: Entity,
+    move_name: Option<&'a String>,
+    on_hit: Option<&'a Vec<Effect>>,
+    attacks: Option<&'a HashMap<Tag, Vec<Effect>>>,
+    contacts: &'a [(usize, Entity)],
+}
+
+impl<'a> Attacker<'a> {
+    fn new(
+        entity: Entity,
+        character: Option<&'a Character>,
+        projectile: Option<&'a Projectile>,
+    ) -> Option<Self> {
+        match (character, projectile) {
+            (Some(character), _) => Some(Self {
+                owner: entity,
+                move_name: character.current_move.as_ref(),
+                on_hit: character.current_move_on_hit.as_ref(),
+                attacks: Some(&character.current_move_attacks),
+                contacts: &character.current_move_contacts,
+            }),
+            (None, Some(projectile)) if !projectile.spent => Some(Self {
+                owner: projectile.owner,
+                move_name: projectile.move_name.as_ref(),
+                on_hit: Some(&projectile.effects),
+                attacks: None,
+                contacts: &[],
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Spends the boxes of `entity` that hit or clashed. A projectile is used up by any of them.
+fn record_contacts(
+    query: &mut Combatants,
+    entity: Entity,
+    contacts: impl IntoIterator<Item = (usize, Entity)>,
+) {
+    let Ok((.., character, projectile, _)) = query.get_mut(entity) else {
+        return;
+    };
+    if let Some(mut character) = character {
+        character.current_move_hit = true;
+        character.current_move_contacts.extend(contacts);
+    } else if let Some(mut projectile) = projectile {
+        projectile.spent = true;
+    }
+}
+
+/// Marks characters touched by an opponent's proximity box, so that holding back guards in
+/// place instead of walking away.
+pub(crate) fn proximity_guard(
+    rules: Res<Rules>,
+    mut query: Query<(&Team, &Position, &Hitboxes, &mut Character)>,
+) {
+    let matrix = &rules.collision_matrix;
+    let attacks: Vec<(Team, FixedPoint, HitboxKind, Bounds)> = query
+        .iter()
+        .flat_map(|(team, pos, hitboxes, _)| {
+            hitboxes
+                .hitboxes
+                .iter()
+                .filter(|hb| matrix.proximity.iter().any(|(kind, _)| *kind == hb.kind))
+                .map(move |hb| (*team, pos.0.x, hb.kind, Bounds::of(pos, hb)))
+        })
+        .collect();
+
+    for (team, pos, hitboxes, mut character) in query.iter_mut() {
+        character.proximity_attack = attacks
+            .iter()
+            .find(|(attack_team, _, kind, bounds)| {
+                attack_team != team
+                    && hitboxes.hitboxes.iter().any(|hb| {
+                        matrix.proximity(*kind, hb.kind) && bounds.overlaps(&Bounds::of(pos, hb))
+                    })
+            })
+            .map(|(_, attacker_x, _, _)| *attacker_x);
+    }
+}
+
+/// The boxes of every character that other bodies can't move into, taken before anyone moves.
+pub(crate) struct PushObstacles(Vec<(Entity, HitboxKind, Bounds)>);
+
+impl PushObstacles {
+    pub fn new<'a>(
+        matrix: &CollisionMatrix,
+        characters: impl Iterator<Item = (Entity, &'a Position, &'a Hitboxes)>,
+    ) -> Self {
+        Self(
+            characters
+                .flat_map(|(entity, pos, hitboxes)| {
+                    hitboxes.hitboxes.iter().map(move |hb| (entity, hb.kind, Bounds::of(pos, hb)))
+                })
+                .filter(|(_, kind, _)| matrix.pushes.iter().any(|(a, b)| a == kind || b == kind))
+                .collect(),
+        )
+    }
+
+    /// Shortens a horizontal move of `entity` by `dx`, so that its `hitboxes` stop against the
+    /// boxes of others instead of moving into them. Boxes that already overlap are left to
+    /// `push_apart`.
+    pub fn limit(
+        &self,
+        matrix: &CollisionMatrix,
+        entity: Entity,
+        pos: &Position,
+        hitboxes: &[Hitbox2],
+        dx: FixedPoint,
+    ) -> FixedPoint {
+        let mut dx = dx;
+        for hitbox in hitboxes {
+            let own = Bounds::of(pos, hitbox);
+            for (other, kind, bounds) in &self.0 {
+                if *other == entity
+                    || !matrix.pushes(hitbox.kind, *kind)
+                    || own.bottom >= bounds.top
+                    || own.top <= bounds.bottom
+                {
+                    continue;
+                }
+                if dx > FixedPoint::ZERO && own.right <= bounds.left {
+                    dx = dx.min(bounds.left - own.right);
+                } else if dx < FixedPoint::ZERO && own.left >= bounds.right {
+                    dx = dx.max(bounds.right - own.left);
                 }
             }
         }
+        dx
     }
+}
+
+/// Moves characters whose bodies overlap apart along x, each by half of the overlap. When one
+/// of them is stopped by a wall, the other one moves the rest of the way.
+pub(crate) fn push_apart(
+    rules: Res<Rules>,
+    stage: Res<Stage>,
+    mut query: Query<(Entity, &mut Position, &Hitboxes)>,
+) {
+    let matrix = &rules.collision_matrix;
+    let pushes = |hb: &&Hitbox2| matrix.pushes.iter().any(|(a, b)| *a == hb.kind || *b == hb.kind);
+    let pairs = broad_phase(query.iter().filter_map(|(entity, pos, hitboxes)| {
+        hitboxes
+            .hitboxes
+            .iter()
+            .filter(pushes)
+            .map(|hitbox| Bounds::of(pos, hitbox))
+            .reduce(Bounds::union)
+            .map(|bounds| (entity, bounds))
+    }));
+
+    for (aentity, bentity) in pairs {
+        let [(_, mut apos, ahitboxes), (_, mut bpos, bhitboxes)] =
+            query.get_many_mut([aentity, bentity]).unwrap();
+        let mut overlap = FixedPoint::ZERO;
+        for ahitbox in &ahitboxes.hitboxes {
+            for bhitbox in &bhitboxes.hitboxes {
+                if !matrix.pushes(ahitbox.kind, bhitbox.kind) {
+                    continue;
+                }
+                let (abounds, bbounds) = (Bounds::of(&apos, ahitbox), Bounds::of(&bpos, bhitbox));
+                if abounds.overlaps(&bbounds) {
+                    let width = abounds.right.min(bbounds.right) - abounds.left.max(bbounds.left);
+                    overlap = overlap.max(width);
+                }
+            }
+        }
+        if overlap == FixedPoint::ZERO {
+            continue;
+        }
 
-    for (entity, mut effects) in effects_to_apply {
-        player_query.get_mut(entity).unwrap().3.effects.append(&mut effects);
+        // Characters standing on the same spot are split by entity, so the result doesn't
+        // depend on anything but the state of the match.
+        let direction = if apos.0.x <= bpos.0.x {
+            FixedPoint::from(-1.0)
+        } else {
+            FixedPoint::from(1.0)
+        };
+        let ax = stage.clamp(
+            apos.0.x + overlap / FixedPoint::from(2.0) * direction,
+            matrix.wall_span(&ahitboxes.hitboxes),
+        );
+        let remaining = overlap - (ax - apos.0.x).abs();
+        apos.0.x = ax;
+        bpos.0.x = stage.clamp(
+            bpos.0.x - remaining * direction,
+            matrix.wall_span(&bhitboxes.hitboxes),
+        );
     }
 }
 
 //  |---|         |OK  |---|    |OK    |---|  |        |---|  |   |---|   |  |-----|
 //         |---|  |      |---|  |    |---|    | |---|         |  |-----|  |   |---|
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::Events;
+
+    use super::*;
+    use crate::{
+        character::{CharacterState, FacingDirection, Guard, Health, Meter},
+        effects::{apply_effects, regenerate_guard},
+        events::{BlockEvent, HitEvent, KoEvent, MoveStartedEvent},
+        vec2::Vec2,
+        Velocity,
+    };
+
+    const HURTBOX: usize = 0;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.init_resource::<Frameticker>();
+        world.insert_resource(Rules::default());
+        world.insert_resource(Stage::default());
+        world.init_resource::<Events<HitEvent>>();
+        world.init_resource::<Events<BlockEvent>>();
+        world.init_resource::<Events<WhiffEvent>>();
+        world.init_resource::<Events<ClashEvent>>();
+        world.init_resource::<Events<MoveStartedEvent>>();
+        world.init_resource::<Events<KoEvent>>();
+        world
+    }
+
+    /// Resolves hits and applies them, like a frame of the match does.
+    fn schedule() -> Schedule {
+        let mut schedule = Schedule::new();
+        schedule.add_systems((collision_resolver, apply_effects).chain());
+        schedule
+    }
+
+    fn fighter(world: &mut World, team: Team, state: CharacterState) -> Entity {
+        let zero = Vec2 {
+            x: FixedPoint::ZERO,
+            y: FixedPoint::ZERO,
+        };
+        world
+            .spawn((
+                team,
+                Collisions { collisions: vec![] },
+                Character {
+                    state,
+                    current_move: Some("Jab".into()),
+                    current_move_on_hit: Some(vec![
+                        Effect::Damage(FixedPoint::from(1.0)),
+                        Effect::JuggleCost(1),
+                    ]),
+                    ..default()
+                },
+                Effects {
+                    effects: vec![],
+                    attacker: None,
+                    move_name: None,
+                },
+                Health {
+                    value: FixedPoint::from(100.0),
+                    max: FixedPoint::from(100.0),
+                },
+                Guard {
+                    value: FixedPoint::from(100.0),
+                },
+                Meter {
+                    value: FixedPoint::ZERO,
+                },
+                Velocity(zero),
+                Position(zero),
+                Hitboxes { hitboxes: vec![] },
+            ))
+            .id()
+    }
+
+    /// Reports box `hitbox_id` of `attacker` overlapping `target`, as `collisions` would.
+    fn overlap(
+        world: &mut World,
+        attacker: Entity,
+        target: Entity,
+        hitbox_id: usize,
+        clash: bool,
+    ) {
+        let teams = [attacker, target].map(|entity| *world.get::<Team>(entity).unwrap());
+        let collision = |other_entity, other_team, did_the_hitting, hitbox_id| Collision {
+            other_entity,
+            other_team,
+            did_the_hitting,
+            clash,
+            hitbox_id,
+            attack: None,
+            priority: 0,
+        };
+        let own = collision(target, teams[1], !clash, hitbox_id);
+        world.get_mut::<Collisions>(attacker).unwrap().collisions.push(own);
+        let other = collision(attacker, teams[0], false, if clash { hitbox_id } else { HURTBOX });
+        world.get_mut::<Collisions>(target).unwrap().collisions.push(other);
+    }
+
+    fn health(world: &World, entity: Entity) -> f32 {
+        world.get::<Health>(entity).unwrap().value.into()
+    }
+
+    #[test]
+    fn overlapping_for_several_frames_hits_once() {
+        let mut world = world();
+        let mut schedule = schedule();
+        let attacker = fighter(&mut world, Team::Team1, CharacterState::Normal);
+        let target = fighter(&mut world, Team::Team2, CharacterState::Idle);
+
+        for _ in 0..5 {
+            overlap(&mut world, attacker, target, 2, false);
+            schedule.run(&mut world);
+        }
+
+        assert_eq!(health(&world, target), 99.0);
+        assert_eq!(world.resource::<Events<HitEvent>>().len(), 1);
+
+        // Another box of the same move is a new hit.
+        overlap(&mut world, attacker, target, 3, false);
+        schedule.run(&mut world);
+        assert_eq!(health(&world, target), 98.0);
+    }
+
+    #[test]
+    fn clashing_for_several_frames_freezes_once() {
+        let mut world = world();
+        let mut schedule = schedule();
+        let a = fighter(&mut world, Team::Team1, CharacterState::Normal);
+        let b = fighter(&mut world, Team::Team2, CharacterState::Normal);
+
+        for frame in 0..5 {
+            overlap(&mut world, a, b, 2, true);
+            schedule.run(&mut world);
+            let mut frameticker = world.resource_mut::<Frameticker>();
+            assert_eq!(frameticker.freeze > 0, frame == 0);
+            frameticker.freeze = 0;
+        }
+
+        assert_eq!(world.resource::<Events<ClashEvent>>().len(), 1);
+    }
+
+    #[test]
+    fn distinct_hits_exhaust_the_juggle_limit() {
+        let mut world = world();
+        let mut schedule = schedule();
+        let limit = world.resource::<Rules>().juggle_limit;
+        let attacker = fighter(&mut world, Team::Team1, CharacterState::Normal);
+        let target = fighter(&mut world, Team::Team2, CharacterState::Launched);
+        let juggle_points = |world: &World| {
+            world.get::<Character>(target).unwrap().combo.juggle_points
+        };
+
+        // Lingering on one box for longer than the limit only costs it once.
+        for _ in 0..limit * 2 {
+            overlap(&mut world, attacker, target, 1, false);
+            schedule.run(&mut world);
+        }
+        assert_eq!(juggle_points(&world), 1);
+
+        for hitbox_id in 2..=limit {
+            overlap(&mut world, attacker, target, hitbox_id, false);
+            schedule.run(&mut world);
+        }
+        assert_eq!(juggle_points(&world), limit);
+        assert_eq!(world.resource::<Events<WhiffEvent>>().len(), 0);
+
+        overlap(&mut world, attacker, target, limit + 1, false);
+        schedule.run(&mut world);
+        assert_eq!(juggle_points(&world), limit);
+        assert_eq!(world.resource::<Events<HitEvent>>().len(), limit);
+        assert_eq!(world.resource::<Events<WhiffEvent>>().len(), 1);
+    }
+
+    #[test]
+    fn projectile_hits_a_character_for_its_owner() {
+        let mut world = world();
+        let mut schedule = schedule();
+        let owner = fighter(&mut world, Team::Team1, CharacterState::Normal);
+        let target = fighter(&mut world, Team::Team2, CharacterState::Idle);
+        let projectile = world
+            .spawn((
+                Team::Team1,
+                Collisions { collisions: vec![] },
+                Projectile {
+                    owner,
+                    move_name: Some("Fireball".into()),
+                    effects: vec![Effect::Damage(FixedPoint::from(5.0))],
+                    spent: false,
+                },
+            ))
+            .id();
+
+        overlap(&mut world, projectile, target, 0, false);
+        schedule.run(&mut world);
+
+        assert_eq!(health(&world, target), 95.0);
+        assert!(world.get::<Projectile>(projectile).unwrap().spent);
+        let hits = world.resource::<Events<HitEvent>>();
+        let hit = hits.iter_current_update_events().next().unwrap();
+        assert_eq!((hit.attacker, hit.move_name.as_deref()), (owner, Some("Fireball")));
+
+        // A spent projectile hits nothing more, while it waits to be removed.
+        overlap(&mut world, projectile, target, 0, false);
+        schedule.run(&mut world);
+        assert_eq!(health(&world, target), 95.0);
+    }
+
+    #[test]
+    fn crushed_defender_can_block_once_the_crush_is_over() {
+        let mut world = world();
+        let mut schedule = Schedule::new();
+        schedule.add_systems((collision_resolver, apply_effects, regenerate_guard).chain());
+        let attacker = fighter(&mut world, Team::Team1, CharacterState::Normal);
+        let defender = fighter(&mut world, Team::Team2, CharacterState::GuardCrush(0));
+        world.get_mut::<Guard>(defender).unwrap().value = FixedPoint::ZERO;
+        schedule.run(&mut world);
+
+        // What `state_manager` does once the crush runs out, with the defender holding back.
+        let mut character = world.get_mut::<Character>(defender).unwrap();
+        character.state = CharacterState::Idle;
+        character.input_dir = crate::AbsoluteDirection::Left;
+
+        overlap(&mut world, attacker, defender, 2, false);
+        schedule.run(&mut world);
+
+        let state = world.get::<Character>(defender).unwrap().state;
+        assert!(!matches!(state, CharacterState::GuardCrush(_)), "{state:?}");
+        assert_eq!(world.resource::<Events<BlockEvent>>().len(), 1);
+    }
+
+    #[test]
+    fn crossed_up_defender_is_pushed_away_from_the_attacker() {
+        let mut world = world();
+        let mut schedule = schedule();
+        let attacker = fighter(&mut world, Team::Team1, CharacterState::Normal);
+        let defender = fighter(&mut world, Team::Team2, CharacterState::Idle);
+        world.get_mut::<Position>(attacker).unwrap().0.x = FixedPoint::from(10.0);
+        world.get_mut::<Character>(attacker).unwrap().current_move_on_hit =
+            Some(vec![Effect::Pushback(FixedPoint::from(1.0))]);
+        // Still facing left after the attacker jumped over to the right.
+        world.get_mut::<Character>(defender).unwrap().facing = FacingDirection::Left;
+
+        overlap(&mut world, attacker, defender, 2, false);
+        schedule.run(&mut world);
+
+        assert_eq!(f32::from(world.get::<Velocity>(defender).unwrap().0.x), -1.0);
+    }
+}
@@ -0,0 +1,98 @@
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::{
+    anim_file::FrameEvent,
+    animation::{Animation, AnimationEvent, Hitboxes},
+    character::{Character, FacingDirection, Team},
+    effects::Effect,
+    fixedpoint::FixedPoint,
+    physics::Collisions,
+    stage::{Span, Stage},
+    vec2::Vec2,
+    Position, Velocity,
+};
+
+/// An attack flying on its own, fired by a `FrameEvent::SpawnProjectile` of its owner's move.
+#[derive(Component)]
+pub struct Projectile {
+    pub owner: Entity,
+    pub move_name: Option<String>,
+    /// Effects of the owner's move at the time it was fired.
+    pub effects: Vec<Effect>,
+    /// Set once it hit or clashed with something, after which it is removed.
+    pub spent: bool,
+}
+
+/// Fires the projectiles of the frames characters entered this tick. The projectile keeps the
+/// boxes of the first frame of its animation and flies in a straight line.
+pub(crate) fn spawn_projectiles(
+    mut commands: Commands,
+    mut events: EventReader<AnimationEvent>,
+    owners: Query<(&Character, &Team, &Position)>,
+    animations: Res<Assets<Animation>>,
+) {
+    for AnimationEvent { entity, event } in events.iter() {
+        let FrameEvent::SpawnProjectile(spawn) = event else {
+            continue;
+        };
+        let Ok((character, team, position)) = owners.get(*entity) else {
+            continue;
+        };
+        let Some(animation) = character
+            .animations
+            .get(&spawn.animation)
+            .and_then(|handle| animations.get(handle))
+        else {
+            warn!("Projectile animation '{}' is not loaded", spawn.animation);
+            continue;
+        };
+
+        let facing = if character.facing == FacingDirection::Right {
+            FixedPoint::from(1.0)
+        } else {
+            FixedPoint::from(-1.0)
+        };
+        let relative = |v: bevy::math::Vec2| Vec2 {
+            x: FixedPoint::from(v.x) * facing,
+            y: FixedPoint::from(v.y),
+        };
+
+        let mut sprite = TextureAtlasSprite::new(0);
+        sprite.anchor = Anchor::BottomCenter;
+        sprite.flip_x = character.facing == FacingDirection::Left;
+        commands.spawn((
+            SpriteSheetBundle {
+                sprite,
+                texture_atlas: animation.spritesheet.image.clone(),
+                ..default()
+            },
+            Projectile {
+                owner: *entity,
+                move_name: character.current_move.clone(),
+                effects: character.current_move_on_hit.clone().unwrap_or_default(),
+                spent: false,
+            },
+            *team,
+            Position(position.0 + relative(spawn.offset)),
+            Velocity(relative(spawn.velocity)),
+            Hitboxes {
+                hitboxes: animation.first_hitboxes(character.facing).to_vec(),
+            },
+            Collisions { collisions: vec![] },
+        ));
+    }
+}
+
+/// Moves projectiles, and removes the ones that hit something or left the stage.
+pub(crate) fn move_projectiles(
+    mut commands: Commands,
+    stage: Res<Stage>,
+    mut query: Query<(Entity, &Projectile, &mut Position, &Velocity)>,
+) {
+    for (entity, projectile, mut position, velocity) in query.iter_mut() {
+        position.0 = position.0 + velocity.0;
+        if projectile.spent || stage.wall_at(position.0.x, Span::POINT).is_some() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
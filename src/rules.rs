@@ -0,0 +1,73 @@
+use bevy::prelude::*;
+
+use crate::{fixedpoint::FixedPoint, physics::CollisionMatrix};
+
+/// Match rules that tune how combat resolves, kept out of the systems that apply them.
+#[derive(Resource)]
+pub struct Rules {
+    /// Fraction of a move's damage dealt on block, for moves without their own chip damage.
+    pub chip_ratio: FixedPoint,
+    /// Whether chip damage is allowed to finish a character off.
+    pub chip_can_ko: bool,
+    pub max_guard: FixedPoint,
+    /// Fraction of a move's damage taken from the guard gauge, for moves without their own
+    /// guard damage.
+    pub guard_damage_ratio: FixedPoint,
+    /// Guard regained every frame the character is not blocking.
+    pub guard_regen: FixedPoint,
+    pub guard_crush_frames: usize,
+    pub juggle_limit: usize,
+    /// Horizontal distance between attacker and defender within which either direction
+    /// blocks, so cross-ups that land nearly on top of the defender stay fair.
+    pub crossup_ambiguity: FixedPoint,
+    pub meter_per_bar: FixedPoint,
+    pub max_bars: usize,
+    /// Meter gained per point of damage dealt, including chip damage.
+    pub meter_per_damage_dealt: FixedPoint,
+    /// Meter gained per point of damage taken, including chip damage.
+    pub meter_per_damage_taken: FixedPoint,
+    pub collision_matrix: CollisionMatrix,
+    pub priority: PriorityRules,
+}
+
+/// How hits that land on the same frame are settled.
+pub struct PriorityRules {
+    /// When two characters strike each other on the same frame, only the box with the higher
+    /// priority lands. Otherwise, or with equal priorities, both hits land as a trade.
+    pub higher_priority_wins: bool,
+    /// Whether a throw wins over a strike landing on the same frame, or the other way around.
+    pub throws_beat_strikes: bool,
+    /// Frames the match freezes for when two attacks clash.
+    pub clash_freeze: usize,
+}
+
+impl Rules {
+    pub fn max_meter(&self) -> FixedPoint {
+        self.meter_per_bar * FixedPoint::from(self.max_bars)
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            chip_ratio: FixedPoint::from(0.2),
+            chip_can_ko: false,
+            max_guard: FixedPoint::from(100.0),
+            guard_damage_ratio: FixedPoint::from(2.0),
+            guard_regen: FixedPoint::from(0.1),
+            guard_crush_frames: 60,
+            juggle_limit: 6,
+            crossup_ambiguity: FixedPoint::from(2.0),
+            meter_per_bar: FixedPoint::from(100.0),
+            max_bars: 3,
+            meter_per_damage_dealt: FixedPoint::from(1.0),
+            meter_per_damage_taken: FixedPoint::from(0.5),
+            collision_matrix: CollisionMatrix::default(),
+            priority: PriorityRules {
+                higher_priority_wins: true,
+                throws_beat_strikes: true,
+                clash_freeze: 10,
+            },
+        }
+    }
+}
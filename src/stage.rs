@@ -0,0 +1,85 @@
+use bevy::prelude::*;
+
+use crate::{fixedpoint::FixedPoint, vec2::Vec2};
+
+#[derive(Resource, Clone, Copy)]
+pub struct Stage {
+    pub left: FixedPoint,
+    pub right: FixedPoint,
+}
+
+impl Default for Stage {
+    fn default() -> Self {
+        Self {
+            left: FixedPoint::from(-100.0),
+            right: FixedPoint::from(100.0),
+        }
+    }
+}
+
+impl Stage {
+    /// Returns the wall a character at `x` is touching or past, if any.
+    pub fn wall_at(&self, x: FixedPoint, span: Span) -> Option<Wall> {
+        if x + span.left <= self.left {
+            Some(Wall::Left)
+        } else if x + span.right >= self.right {
+            Some(Wall::Right)
+        } else {
+            None
+        }
+    }
+
+    /// Moves a character at `x` back inside the walls.
+    pub fn clamp(&self, x: FixedPoint, span: Span) -> FixedPoint {
+        x.max(self.left - span.left).min(self.right - span.right)
+    }
+
+    /// Whether a character at `x` being pushed in `direction` (negative is left) is stuck
+    /// against a wall.
+    pub fn is_cornered(&self, x: FixedPoint, span: Span, direction: FixedPoint) -> bool {
+        match self.wall_at(x, span) {
+            Some(Wall::Left) => direction < FixedPoint::ZERO,
+            Some(Wall::Right) => direction > FixedPoint::ZERO,
+            None => false,
+        }
+    }
+}
+
+/// How far a character reaches to each side of its position as far as the walls are
+/// concerned, from its collision boxes. Characters without any stop at their position.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Span {
+    pub left: FixedPoint,
+    pub right: FixedPoint,
+}
+
+impl Span {
+    pub const POINT: Span = Span {
+        left: FixedPoint::ZERO,
+        right: FixedPoint::ZERO,
+    };
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Wall {
+    Left,
+    Right,
+}
+
+impl Wall {
+    /// Sign of the direction pointing away from this wall.
+    pub fn away(&self) -> FixedPoint {
+        match self {
+            Wall::Left => FixedPoint::from(1.0),
+            Wall::Right => FixedPoint::from(-1.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WallReaction {
+    /// Velocity after bouncing, with `x` pointing away from the wall.
+    Bounce(Vec2),
+    /// Number of frames the character stays stuck to the wall.
+    Splat(usize),
+}
@@ -0,0 +1,22 @@
+/// A name hashed to a number, cheap to copy and compare. Used for the names attached to
+/// hitboxes so that nothing is allocated when they are handed around every frame.
+///
+/// The hash is computed from the name alone, so a name gets the same tag in every run and on
+/// every machine, whatever order files are loaded in.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Tag(u64);
+
+impl Tag {
+    /// Hashes `name` with 64-bit FNV-1a.
+    pub const fn new(name: &str) -> Tag {
+        let bytes = name.as_bytes();
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut i = 0;
+        while i < bytes.len() {
+            hash ^= bytes[i] as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+            i += 1;
+        }
+        Tag(hash)
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{
-    character::{Character, Health, Team},
+    character::{Character, Health, Meter, Team},
     fixedpoint::FixedPoint,
+    rules::Rules,
     Frameticker,
 };
 use bevy::prelude::*;
@@ -8,6 +9,9 @@ use bevy::prelude::*;
 #[derive(Component)]
 pub struct KoTextMarker;
 
+#[derive(Component)]
+pub struct MeterBarMarker;
+
 pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands
         .spawn(NodeBundle {
@@ -91,18 +95,86 @@ pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
                     ));
                 });
         });
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::width(Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(0.0),
+                    ..default()
+                },
+                padding: UiRect {
+                    left: Val::Px(25.0),
+                    right: Val::Px(25.0),
+                    top: Val::Px(25.0),
+                    bottom: Val::Px(25.0),
+                },
+                justify_content: JustifyContent::SpaceBetween,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            for (team, justify_content) in [
+                (Team::Team1, JustifyContent::Start),
+                (Team::Team2, JustifyContent::End),
+            ] {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            size: Size {
+                                width: Val::Px(300.0),
+                                height: Val::Px(20.0),
+                            },
+                            justify_content,
+                            ..default()
+                        },
+                        background_color: Color::DARK_GRAY.into(),
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn((
+                            NodeBundle {
+                                style: Style {
+                                    size: Size::width(Val::Percent(0.0)),
+                                    ..default()
+                                },
+                                background_color: Color::YELLOW.into(),
+                                ..default()
+                            },
+                            MeterBarMarker,
+                            team,
+                        ));
+                    });
+            }
+        });
+}
+
+/// How full a bar is, empty when it can't hold anything.
+fn percent(value: FixedPoint, max: FixedPoint) -> Val {
+    if max == FixedPoint::ZERO {
+        return Val::Percent(0.0);
+    }
+    Val::Percent(f32::from(value / max) * 100.0)
 }
 
 pub(crate) fn ui_system(
     mut frame_ticker: ResMut<Frameticker>,
-    player_query: Query<(&Health, &Team), With<Character>>,
-    mut healths: Query<(&mut Style, &Team)>,
+    rules: Res<Rules>,
+    player_query: Query<(&Health, &Meter, &Team), With<Character>>,
+    mut healths: Query<(&mut Style, &Team), Without<MeterBarMarker>>,
+    mut meters: Query<(&mut Style, &Team), With<MeterBarMarker>>,
     mut ko_visibility: Query<&mut Visibility, With<KoTextMarker>>,
     mut text: Query<&mut Text, With<KoTextMarker>>,
 ) {
-    for (health, team) in player_query.iter() {
+    for (health, meter, team) in player_query.iter() {
         let (mut hp_style, _) = healths.iter_mut().find(|(_, tm)| *tm == team).unwrap();
-        hp_style.size.width = Val::Percent(health.value.into());
+        hp_style.size.width = percent(health.value, health.max);
+
+        let (mut meter_style, _) = meters.iter_mut().find(|(_, tm)| *tm == team).unwrap();
+        meter_style.size.width = percent(meter.value, rules.max_meter());
 
         if health.value <= FixedPoint::ZERO {
             *ko_visibility.single_mut() = Visibility::Inherited;
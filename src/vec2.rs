@@ -1,8 +1,10 @@
 use std::ops::{Add, Sub, Mul};
 
+use serde::{Deserialize, Serialize};
+
 use crate::fixedpoint::FixedPoint;
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Vec2 {
     pub x: FixedPoint,
     pub y: FixedPoint,